@@ -0,0 +1,20 @@
+//! Decode arbitrary bytes, checking that whatever decodes encodes back to the same bytes.
+
+#![no_main]
+use chip_8::emulator::instruction::Instruction;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(instruction) = Instruction::decode(data) {
+        let bytes = instruction.to_bytes();
+        assert_eq!(bytes[..], data[..bytes.len()]);
+        let _ = instruction.to_string();
+    }
+
+    for pair in data.chunks_exact(2) {
+        let opcode = u16::from_be_bytes([pair[0], pair[1]]);
+        if let Ok(instruction) = Instruction::from_u16(opcode) {
+            assert_eq!(instruction.to_u16(), opcode);
+        }
+    }
+});
@@ -0,0 +1,53 @@
+//! Load and run arbitrary programs for a bounded number of steps.
+//! The first byte picks the quirks preset, and the rest is the program.
+
+#![no_main]
+use chip_8::emulator::input::DummyInput;
+use chip_8::emulator::output::EmulatorOutput;
+use chip_8::emulator::quirks::Quirks;
+use chip_8::emulator::Emulator;
+use libfuzzer_sys::fuzz_target;
+
+const MAX_STEPS: usize = 10_000;
+const CYCLES_PER_FRAME: usize = 10;
+
+/// A screen in a flat array, since looking pixels up in the `HashMap` of `DummyOutput`
+/// makes scrolling and clearing slow enough to hold back the fuzzer.
+struct Screen([[u8; 128]; 64]);
+
+impl EmulatorOutput for Screen {
+    fn set(&mut self, x: usize, y: usize, state: u8) {
+        self.0[y][x] = state;
+    }
+    fn get(&self, x: usize, y: usize) -> u8 {
+        self.0[y][x]
+    }
+    fn clear(&mut self) {
+        self.0 = [[0; 128]; 64];
+    }
+    fn refresh(&mut self) {}
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (preset, program) = match data.split_first() {
+        Some((&preset, program)) => (preset, program),
+        None => return,
+    };
+    let quirks = Quirks::PRESETS
+        // Spread the first byte evenly over the presets, with the default quirks after them
+        .get(preset as usize % (Quirks::PRESETS.len() + 1))
+        .map(|(_, quirks)| *quirks)
+        .unwrap_or_default();
+
+    let mut emulator = Emulator::with_quirks(DummyInput, Screen([[0; 128]; 64]), quirks);
+    emulator.load(program);
+    for step in 1..=MAX_STEPS {
+        // Faults are fine, as long as they don't panic
+        if emulator.step().is_err() || emulator.has_exited() {
+            break;
+        }
+        if step % CYCLES_PER_FRAME == 0 || emulator.is_waiting_for_vblank() {
+            emulator.end_frame();
+        }
+    }
+});
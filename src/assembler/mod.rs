@@ -0,0 +1,550 @@
+use crate::emulator::emulator::PC_START;
+use crate::emulator::instruction::{Addr, Const, Instruction, Reg};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Names that refer to operands, and can't be used for labels or constants.
+const RESERVED: [&str; 9] = ["I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R"];
+
+/// An error in the assembly source, at a 1-based line and column.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// A single line of assembled output.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Item {
+    Instruction(Instruction),
+    /// Bytes from a `db` or `dw` directive
+    Data(Vec<u8>),
+}
+
+/// An assembled program, as instructions and data at the addresses they are loaded at.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Program {
+    pub items: Vec<(u16, Item)>,
+}
+
+impl Program {
+    /// The bytes of the program, to be loaded at `PC_START`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (_, item) in &self.items {
+            match item {
+                Item::Instruction(instruction) => bytes.extend(instruction.to_bytes()),
+                Item::Data(data) => bytes.extend_from_slice(data),
+            }
+        }
+        bytes
+    }
+}
+
+/// A piece of a line of source, along with where it is.
+#[derive(Debug, Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Token<'a> {
+    /// The part of `line` from byte `start` to `end`, without surrounding whitespace.
+    fn new(line: &'a str, line_no: usize, start: usize, end: usize) -> Token<'a> {
+        let text = &line[start..end];
+        let leading = text.len() - text.trim_start().len();
+        Token {
+            text: text.trim(),
+            line: line_no,
+            column: line[..start + leading].chars().count() + 1,
+        }
+    }
+
+    /// The part of this token starting at byte `start`, without surrounding whitespace.
+    fn rest(&self, start: usize) -> Token<'a> {
+        let text = &self.text[start..];
+        let leading = text.len() - text.trim_start().len();
+        Token {
+            text: text.trim(),
+            line: self.line,
+            column: self.column + self.text[..start + leading].chars().count(),
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        })
+    }
+}
+
+/// A line that produces output.
+enum Statement<'a> {
+    Instruction {
+        mnemonic: Token<'a>,
+        operands: Vec<Token<'a>>,
+    },
+    Bytes(Vec<Token<'a>>),
+    Words(Vec<Token<'a>>),
+}
+
+impl Statement<'_> {
+    /// The number of bytes the statement assembles to.
+    fn size(&self) -> usize {
+        match self {
+            // `LD I, long NNNN` is the only four byte instruction
+            Statement::Instruction { operands, .. } => match operands.get(1) {
+                Some(operand) if long_operand(operand).is_some() => 4,
+                _ => 2,
+            },
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => 2 * values.len(),
+        }
+    }
+}
+
+enum Symbol<'a> {
+    Label(u16),
+    Constant(Token<'a>),
+}
+
+/// An operand of an instruction.
+enum Operand<'a> {
+    V(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Token<'a>),
+    Value(Token<'a>),
+}
+
+/// The value of `long NNNN`, if the operand is one.
+fn long_operand<'a>(token: &Token<'a>) -> Option<Token<'a>> {
+    let (word, _) = token.text.split_at(token.text.find(char::is_whitespace)?);
+    if word.eq_ignore_ascii_case("long") {
+        Some(token.rest(word.len()))
+    } else {
+        None
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn register(text: &str) -> Option<u8> {
+    match text.as_bytes() {
+        [b'V' | b'v', digit] => (*digit as char).to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn is_reserved(text: &str) -> bool {
+    register(text).is_some() || RESERVED.iter().any(|r| r.eq_ignore_ascii_case(text))
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Assemble a program from source, one statement per line.
+///
+/// Every line may start with a `label:` and end with a `; comment`.
+/// A statement is either an instruction, such as `LD V0, 0x12` or `DRW VA, VB, 5`,
+/// using the mnemonics `Instruction` is displayed with,
+/// a `db` or `dw` directive followed by comma separated bytes or big endian words,
+/// or a constant definition such as `SPEED = 4`.
+///
+/// Values are decimal, `0x` hexadecimal or `0b` binary numbers,
+/// names of labels or constants, or sums and differences of these.
+/// Labels can be used before they are defined.
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+    };
+
+    // First find the address of every label, then assemble the statements
+    let mut statements = Vec::new();
+    let mut address = PC_START as usize;
+    for (line_no, line) in source.lines().enumerate() {
+        if let Some(statement) = assembler.parse_line(line, line_no + 1, address)? {
+            let size = statement.size();
+            statements.push((address, statement));
+            address += size;
+            if address > 0x10000 {
+                return Token::new(line, line_no + 1, 0, line.len())
+                    .error("program does not fit in memory");
+            }
+        }
+    }
+
+    let items = statements
+        .iter()
+        .map(|(address, statement)| Ok((*address as u16, assembler.assemble(statement)?)))
+        .collect::<Result<_, _>>()?;
+    Ok(Program { items })
+}
+
+struct Assembler<'a> {
+    symbols: HashMap<String, Symbol<'a>>,
+}
+
+impl<'a> Assembler<'a> {
+    fn define(&mut self, name: Token<'a>, symbol: Symbol<'a>) -> Result<(), AssembleError> {
+        if !is_identifier(name.text) || is_reserved(name.text) {
+            return name.error(format!("invalid name {:?}", name.text));
+        }
+        if self.symbols.contains_key(name.text) {
+            return name.error(format!("{} is already defined", name.text));
+        }
+        self.symbols.insert(name.text.to_string(), symbol);
+        Ok(())
+    }
+
+    /// Parse a line, defining its label and constant,
+    /// and return the statement if it produces output.
+    fn parse_line(
+        &mut self,
+        line: &'a str,
+        line_no: usize,
+        address: usize,
+    ) -> Result<Option<Statement<'a>>, AssembleError> {
+        let end = line.find(';').unwrap_or(line.len());
+        let mut start = 0;
+
+        if let Some(colon) = line[..end].find(':') {
+            let label = Token::new(line, line_no, 0, colon);
+            self.define(label, Symbol::Label(address as u16))?;
+            start = colon + 1;
+        }
+
+        let statement = Token::new(line, line_no, start, end);
+        if statement.text.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(equals) = line[start..end].find('=') {
+            let name = Token::new(line, line_no, start, start + equals);
+            let value = Token::new(line, line_no, start + equals + 1, end);
+            self.define(name, Symbol::Constant(value))?;
+            return Ok(None);
+        }
+
+        // Split the mnemonic from the comma separated operands
+        let text_start = end - line[start..end].trim_start().len();
+        let mnemonic_len = statement
+            .text
+            .find(char::is_whitespace)
+            .unwrap_or(statement.text.len());
+        let mnemonic = Token {
+            text: &statement.text[..mnemonic_len],
+            ..statement
+        };
+        let mut operands = Vec::new();
+        let mut operand_start = text_start + mnemonic_len;
+        if !line[operand_start..end].trim().is_empty() {
+            for part in line[operand_start..end].split(',') {
+                let operand = Token::new(line, line_no, operand_start, operand_start + part.len());
+                if operand.text.is_empty() {
+                    return operand.error("expected an operand");
+                }
+                operands.push(operand);
+                operand_start += part.len() + 1;
+            }
+        }
+
+        let statement = match mnemonic.text.to_ascii_lowercase().as_str() {
+            "db" => Statement::Bytes(operands),
+            "dw" => Statement::Words(operands),
+            _ => Statement::Instruction { mnemonic, operands },
+        };
+        Ok(Some(statement))
+    }
+
+    /// Evaluate a sum or difference of numbers and symbols.
+    fn value(&self, token: &Token, depth: usize) -> Result<i64, AssembleError> {
+        if depth > self.symbols.len() {
+            return token.error("circular constant definition");
+        }
+        if token.text.is_empty() {
+            return token.error("expected a value");
+        }
+
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut start = 0;
+        let bytes = token.text.as_bytes();
+        for end in 0..=bytes.len() {
+            if end < bytes.len() && !(end > start && (bytes[end] == b'+' || bytes[end] == b'-')) {
+                continue;
+            }
+            let term = Token {
+                text: token.text[start..end].trim(),
+                ..token.rest(start)
+            };
+            let value = if let Some(number) = parse_number(term.text) {
+                number
+            } else {
+                match self.symbols.get(term.text) {
+                    Some(Symbol::Label(address)) => *address as i64,
+                    Some(Symbol::Constant(value)) => self.value(value, depth + 1)?,
+                    None if is_identifier(term.text) => {
+                        return term.error(format!("undefined name {}", term.text))
+                    }
+                    None => return term.error(format!("invalid value {:?}", term.text)),
+                }
+            };
+            total = total.saturating_add(value.saturating_mul(sign));
+            if end < bytes.len() {
+                sign = if bytes[end] == b'+' { 1 } else { -1 };
+                start = end + 1;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Evaluate a value that must be within `min..=max`.
+    fn bounded(&self, token: &Token, min: i64, max: i64, what: &str) -> Result<i64, AssembleError> {
+        let value = self.value(token, 0)?;
+        if value < min || value > max {
+            return token.error(format!("{} {} is out of range", what, value));
+        }
+        Ok(value)
+    }
+
+    /// A byte, where negative numbers are in two's complement.
+    fn byte(&self, token: &Token) -> Result<u8, AssembleError> {
+        Ok(self.bounded(token, -0x80, 0xFF, "byte")? as u8)
+    }
+
+    fn nibble(&self, token: &Token) -> Result<Const, AssembleError> {
+        Ok(Const(self.bounded(token, 0, 0xF, "nibble")? as u8))
+    }
+
+    fn constant(&self, token: &Token) -> Result<Const, AssembleError> {
+        Ok(Const(self.byte(token)?))
+    }
+
+    fn addr(&self, token: &Token) -> Result<Addr, AssembleError> {
+        Ok(Addr(self.bounded(token, 0, 0xFFF, "address")? as u16))
+    }
+
+    fn operand(token: Token<'a>) -> Operand<'a> {
+        if let Some(x) = register(token.text) {
+            return Operand::V(x);
+        }
+        if let Some(value) = long_operand(&token) {
+            return Operand::Long(value);
+        }
+        match token.text.to_ascii_uppercase().as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            _ => Operand::Value(token),
+        }
+    }
+
+    fn assemble(&self, statement: &Statement<'a>) -> Result<Item, AssembleError> {
+        let (mnemonic, operands) = match statement {
+            Statement::Bytes(values) => {
+                let bytes = values.iter().map(|value| self.byte(value));
+                return Ok(Item::Data(bytes.collect::<Result<_, _>>()?));
+            }
+            Statement::Words(values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    let word = self.bounded(value, -0x8000, 0xFFFF, "word")? as u16;
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+                return Ok(Item::Data(bytes));
+            }
+            Statement::Instruction { mnemonic, operands } => (mnemonic, operands),
+        };
+
+        use Operand::*;
+        let operands: Vec<_> = operands.iter().map(|token| Self::operand(*token)).collect();
+        let instruction = match (mnemonic.text.to_ascii_uppercase().as_str(), &operands[..]) {
+            ("SCD", [Value(n)]) => Instruction::ScrollDown(self.nibble(n)?),
+            ("SCU", [Value(n)]) => Instruction::ScrollUp(self.nibble(n)?),
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::Return,
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("JP", [Value(nnn)]) => Instruction::Goto(self.addr(nnn)?),
+            ("JP", [V(0), Value(nnn)]) => Instruction::SetPcToV0PlusAddr(self.addr(nnn)?),
+            ("CALL", [Value(nnn)]) => Instruction::Call(self.addr(nnn)?),
+            ("SE", [V(x), V(y)]) => Instruction::IfRegEqReg(Reg(*x), Reg(*y)),
+            ("SE", [V(x), Value(nn)]) => Instruction::IfRegEqConst(Reg(*x), self.constant(nn)?),
+            ("SNE", [V(x), V(y)]) => Instruction::IfRegNeqReg(Reg(*x), Reg(*y)),
+            ("SNE", [V(x), Value(nn)]) => Instruction::IfRegNeqConst(Reg(*x), self.constant(nn)?),
+            ("SAVE", [V(x), V(y)]) => Instruction::SaveRegRange(Reg(*x), Reg(*y)),
+            ("LOAD", [V(x), V(y)]) => Instruction::LoadRegRange(Reg(*x), Reg(*y)),
+            ("LD", [V(x), V(y)]) => Instruction::SetRegToReg(Reg(*x), Reg(*y)),
+            ("LD", [V(x), Value(nn)]) => Instruction::SetRegToConst(Reg(*x), self.constant(nn)?),
+            ("LD", [I, Value(nnn)]) => Instruction::SetI(self.addr(nnn)?),
+            ("LD", [I, Long(nnnn)]) => {
+                let nnnn = self.bounded(nnnn, 0, 0xFFFF, "address")?;
+                Instruction::SetILong(Addr(nnnn as u16))
+            }
+            ("LD", [V(x), Dt]) => Instruction::SetRegToDelayTimer(Reg(*x)),
+            ("LD", [V(x), K]) => Instruction::SetRegToGetKey(Reg(*x)),
+            ("LD", [Dt, V(x)]) => Instruction::SetDelayTimerToReg(Reg(*x)),
+            ("LD", [St, V(x)]) => Instruction::SetSoundTimerToReg(Reg(*x)),
+            ("LD", [F, V(x)]) => Instruction::SetIToSpriteAddrVx(Reg(*x)),
+            ("LD", [Hf, V(x)]) => Instruction::SetIToBigSpriteAddrVx(Reg(*x)),
+            ("LD", [B, V(x)]) => Instruction::SetIToBcdOfReg(Reg(*x)),
+            ("LD", [IndirectI, V(x)]) => Instruction::RegDump(Reg(*x)),
+            ("LD", [V(x), IndirectI]) => Instruction::RegLoad(Reg(*x)),
+            ("LD", [R, V(x)]) => Instruction::SaveFlags(Reg(*x)),
+            ("LD", [V(x), R]) => Instruction::LoadFlags(Reg(*x)),
+            ("ADD", [V(x), V(y)]) => Instruction::IncRegByReg(Reg(*x), Reg(*y)),
+            ("ADD", [V(x), Value(nn)]) => Instruction::IncRegByConst(Reg(*x), self.constant(nn)?),
+            ("ADD", [I, V(x)]) => Instruction::AddRegToI(Reg(*x)),
+            ("OR", [V(x), V(y)]) => Instruction::BitwiseOr(Reg(*x), Reg(*y)),
+            ("AND", [V(x), V(y)]) => Instruction::BitwiseAnd(Reg(*x), Reg(*y)),
+            ("XOR", [V(x), V(y)]) => Instruction::BitwiseXor(Reg(*x), Reg(*y)),
+            ("SUB", [V(x), V(y)]) => Instruction::DecRegByReg(Reg(*x), Reg(*y)),
+            ("SUBN", [V(x), V(y)]) => Instruction::SetVxVyMinusVx(Reg(*x), Reg(*y)),
+            // Shifting Vx by itself gives the same result whether or not the shift uses Vy
+            ("SHR", [V(x)]) => Instruction::BitshiftRight(Reg(*x), Reg(*x)),
+            ("SHR", [V(x), V(y)]) => Instruction::BitshiftRight(Reg(*x), Reg(*y)),
+            ("SHL", [V(x)]) => Instruction::BitshiftLeft(Reg(*x), Reg(*x)),
+            ("SHL", [V(x), V(y)]) => Instruction::BitshiftLeft(Reg(*x), Reg(*y)),
+            ("RND", [V(x), Value(nn)]) => Instruction::SetVxRand(Reg(*x), self.constant(nn)?),
+            ("DRW", [V(x), V(y), Value(n)]) => Instruction::Draw(Reg(*x), Reg(*y), self.nibble(n)?),
+            ("SKP", [V(x)]) => Instruction::IfKeyEqVx(Reg(*x)),
+            ("SKNP", [V(x)]) => Instruction::IfKeyNeqVx(Reg(*x)),
+            ("PLANE", [Value(n)]) => Instruction::SelectPlanes(self.nibble(n)?),
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("PITCH", [V(x)]) => Instruction::SetPitchToReg(Reg(*x)),
+            (
+                "SCD" | "SCU" | "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "JP"
+                | "CALL" | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND" | "XOR"
+                | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE"
+                | "AUDIO" | "PITCH",
+                _,
+            ) => return mnemonic.error(format!("invalid operands for {}", mnemonic.text)),
+            _ => return mnemonic.error(format!("unknown mnemonic {}", mnemonic.text)),
+        };
+        Ok(Item::Instruction(instruction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::disassembler::Disassembly;
+    use proptest::prelude::*;
+
+    fn error(source: &str) -> (usize, usize) {
+        let error = assemble(source).unwrap_err();
+        (error.line, error.column)
+    }
+
+    #[test]
+    fn labels_constants_and_data() {
+        let source = "
+            ROWS = 2 ; constant
+        start:
+            LD I, sprite
+            LD V0, ROWS + 1
+            DRW V0, V0, ROWS
+            JP start
+        sprite: db 0xFF, 0b10000001
+            dw sprite - 2, 0x1234
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program.items[0],
+            (0x200, Item::Instruction(Instruction::SetI(Addr(0x208))))
+        );
+        assert_eq!(
+            program.to_bytes(),
+            [0xA2, 0x08, 0x60, 0x03, 0xD0, 0x02, 0x12, 0x00, 0xFF, 0x81, 0x02, 0x06, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn long_set_i_takes_four_bytes() {
+        let program = assemble("LD I, long end\nend:").unwrap();
+        assert_eq!(program.to_bytes(), [0xF0, 0x00, 0x02, 0x04]);
+    }
+
+    #[test]
+    fn negative_bytes_are_twos_complement() {
+        let program = assemble("add v1, -1").unwrap();
+        assert_eq!(program.to_bytes(), [0x71, 0xFF]);
+    }
+
+    #[test]
+    fn errors_have_line_and_column() {
+        assert_eq!(error("CLS\n  FOO V0"), (2, 3));
+        assert_eq!(error("  LD V0,  nowhere"), (1, 11));
+        assert_eq!(error("LD V0, 0x100"), (1, 8));
+        assert_eq!(error("DRW V0, V1"), (1, 1));
+        assert_eq!(error("a:\n a: CLS"), (2, 2));
+        assert_eq!(error("LD V0, 1 +"), (1, 11));
+        assert_eq!(error("X = Y\nY = X\nLD V0, X"), (1, 5));
+        assert_eq!(error("B = 1"), (1, 1));
+    }
+
+    #[test]
+    fn every_instruction_reassembles() {
+        for opcode in 0..=0xFFFF {
+            if let Ok(instruction) = Instruction::from_u16(opcode) {
+                let program = assemble(&instruction.to_string()).unwrap();
+                assert_eq!(
+                    program.to_bytes(),
+                    instruction.to_bytes(),
+                    "{}",
+                    instruction
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn disassembly_reassembles(program in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let disassembly = Disassembly::new(&program).to_string();
+            prop_assert_eq!(assemble(&disassembly).unwrap().to_bytes(), program);
+        }
+    }
+}
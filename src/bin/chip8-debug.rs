@@ -0,0 +1,306 @@
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use chip_8::debugger::{Debugger, Pause, Watchpoint};
+use chip_8::emulator::fault::Fault;
+use chip_8::emulator::input::DummyInput;
+use chip_8::emulator::output::{DummyOutput, EmulatorOutput};
+use chip_8::emulator::quirks::Quirks;
+use chip_8::emulator::Emulator;
+
+/// The most instructions to run when continuing, in case no breakpoint is ever reached.
+const CONTINUE_LIMIT: usize = 10_000_000;
+
+/// The number of instructions shown before and after the program counter.
+const CONTEXT_BEFORE: u16 = 4;
+const CONTEXT_AFTER: u16 = 6;
+
+const HELP: &str = "\
+step [n]       (s)  execute n instructions, 1 by default
+next           (n)  execute an instruction, or a whole subroutine if it is a call
+continue       (c)  run until a breakpoint is reached or the program exits
+break [addr]   (b)  add a breakpoint at addr, or list the breakpoints
+delete <addr>  (d)  remove the breakpoint at addr
+watch               list the watchpoints
+watch r <addr>      pause after the byte at addr is read
+watch w <addr>      pause after the byte at addr is written
+watch v<x> <value>  pause after Vx is changed to value
+watch i <start> <end>
+                    pause after I is changed to point into start up to end
+unwatch <n>         remove the nth watchpoint
+show                print the registers and the code around the program counter
+screen              print the screen
+quit           (q)  stop debugging
+An empty line repeats the previous command.";
+
+/// Step through a CHIP-8 program, stopping at breakpoints to inspect its state.
+#[derive(StructOpt)]
+struct Opt {
+    /// The program to debug
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// The quirks preset to run the program with (vip, chip48, schip, schip-legacy or xochip)
+    #[structopt(long)]
+    quirks: Option<Quirks>,
+
+    /// The number of instructions to execute per frame, at 60 frames per second
+    #[structopt(long, default_value = "10")]
+    cycles_per_frame: usize,
+}
+
+type ProgramDebugger = Debugger<DummyInput, DummyOutput>;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let program = std::fs::read(&opt.input)?;
+    let quirks = opt.quirks.unwrap_or_default();
+    let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+    emulator.load(&program);
+    let mut debugger = Debugger::new(emulator, opt.cycles_per_frame);
+
+    println!("Type help for a list of commands.");
+    show(&debugger);
+
+    let stdin = io::stdin();
+    let mut previous = String::new();
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = previous.clone();
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(None),
+            ["q" | "quit"] => break,
+            ["help"] => {
+                println!("{}", HELP);
+                Ok(None)
+            }
+            ["show"] => Ok(Some(Pause::Done)),
+            ["screen"] => {
+                print_screen(&debugger);
+                Ok(None)
+            }
+            ["s" | "step"] => debugger.step().map(Some),
+            ["s" | "step", n] => match n.parse::<usize>() {
+                Ok(n) => step(&mut debugger, n).map(Some),
+                Err(_) => {
+                    println!("Invalid count {}", n);
+                    Ok(None)
+                }
+            },
+            ["n" | "next"] => debugger.step_over(CONTINUE_LIMIT).map(Some),
+            ["c" | "continue"] => debugger.run(CONTINUE_LIMIT).map(Some),
+            ["b" | "break"] => {
+                for address in debugger.breakpoints() {
+                    println!("{:#05X}", address);
+                }
+                Ok(None)
+            }
+            ["b" | "break", address] => {
+                if let Some(address) = parse_address(address) {
+                    debugger.add_breakpoint(address);
+                }
+                Ok(None)
+            }
+            ["d" | "delete", address] => {
+                if let Some(address) = parse_address(address) {
+                    if !debugger.remove_breakpoint(address) {
+                        println!("No breakpoint at {:#05X}", address);
+                    }
+                }
+                Ok(None)
+            }
+            ["watch"] => {
+                for (n, watchpoint) in debugger.watchpoints().enumerate() {
+                    println!("{}: {}", n + 1, watchpoint);
+                }
+                Ok(None)
+            }
+            ["watch", args @ ..] => {
+                match parse_watchpoint(args) {
+                    Some(watchpoint) => {
+                        debugger.add_watchpoint(watchpoint);
+                    }
+                    None => println!("Invalid watchpoint, type help for the syntax"),
+                }
+                Ok(None)
+            }
+            ["unwatch", n] => {
+                let watchpoint = n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| debugger.watchpoints().nth(n.wrapping_sub(1)));
+                match watchpoint {
+                    Some(watchpoint) => {
+                        debugger.remove_watchpoint(watchpoint);
+                    }
+                    None => println!("No watchpoint {}", n),
+                }
+                Ok(None)
+            }
+            _ => {
+                println!(
+                    "Unknown command {:?}, type help for a list of commands",
+                    line.trim()
+                );
+                Ok(None)
+            }
+        };
+        previous = line;
+
+        match result {
+            Ok(None) => {}
+            Ok(Some(pause)) => {
+                match pause {
+                    Pause::Breakpoint(address) => println!("Breakpoint at {:#05X}", address),
+                    Pause::Exited => println!("The program has exited"),
+                    Pause::Watchpoint { watchpoint, pc } => {
+                        println!("Watchpoint {} at {:#05X}", watchpoint, pc)
+                    }
+                    Pause::Done => {}
+                }
+                show(&debugger);
+            }
+            Err(fault) => {
+                println!("{}", fault);
+                show(&debugger);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Step `n` instructions, stopping early if the program exits.
+fn step(debugger: &mut ProgramDebugger, n: usize) -> Result<Pause, Fault> {
+    for _ in 0..n {
+        if let Pause::Exited = debugger.step()? {
+            return Ok(Pause::Exited);
+        }
+    }
+    Ok(Pause::Done)
+}
+
+/// Parse the arguments of `watch`, where every number is hexadecimal.
+fn parse_watchpoint(args: &[&str]) -> Option<Watchpoint> {
+    let hex = |text: &str| u16::from_str_radix(text.trim_start_matches("0x"), 16).ok();
+    match args {
+        ["r", address] => Some(Watchpoint::Read(hex(address)?)),
+        ["w", address] => Some(Watchpoint::Write(hex(address)?)),
+        ["i", start, end] => Some(Watchpoint::I {
+            start: hex(start)?,
+            end: hex(end)?,
+        }),
+        [register, value] => {
+            let register = register.strip_prefix(&['v', 'V'][..])?;
+            let register = u8::from_str_radix(register, 16).ok().filter(|x| *x < 16)?;
+            let value = u8::from_str_radix(value.trim_start_matches("0x"), 16).ok()?;
+            Some(Watchpoint::Register { register, value })
+        }
+        _ => None,
+    }
+}
+
+/// Parse an address such as `0x2A4` or `2A4`, which is always hexadecimal.
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x");
+    match u16::from_str_radix(digits, 16) {
+        Ok(address) => Some(address),
+        Err(_) => {
+            println!("Invalid address {}", text);
+            None
+        }
+    }
+}
+
+/// Print the registers, stack, timers and the instructions around the program counter.
+fn show(debugger: &ProgramDebugger) {
+    let emulator = debugger.emulator();
+    let registers: Vec<_> = emulator
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(x, value)| format!("V{:X}={:02X}", x, value))
+        .collect();
+    println!("{}", registers[..8].join(" "));
+    println!("{}", registers[8..].join(" "));
+    let (delay, sound) = emulator.timers();
+    let stack: Vec<_> = emulator
+        .stack()
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    println!(
+        "I={:03X} PC={:03X} SP={:02X} DT={:02X} ST={:02X} stack=[{}]",
+        emulator.i(),
+        emulator.program_counter(),
+        emulator.stack().len(),
+        delay,
+        sound,
+        stack.join(" ")
+    );
+
+    // Instructions can't be found reliably going backwards,
+    // so assume the ones before the program counter are two bytes
+    let pc = emulator.program_counter();
+    let mut address = pc.saturating_sub(2 * CONTEXT_BEFORE);
+    while address < pc || address - pc < 2 * CONTEXT_AFTER {
+        let marker = if address == pc { '>' } else { ' ' };
+        let breakpoint = if debugger.breakpoints().any(|b| b == address) {
+            '*'
+        } else {
+            ' '
+        };
+        match debugger.instruction_at(address) {
+            Some(instruction) => {
+                println!("{}{} {:03X}: {}", marker, breakpoint, address, instruction);
+                let size = if address < pc { 2 } else { instruction.size() };
+                address = address.wrapping_add(size);
+            }
+            None => {
+                let start = address as usize;
+                let bytes = match emulator.memory().get(start..start + 2) {
+                    Some(bytes) => bytes,
+                    None => break,
+                };
+                let bytes: Vec<_> = bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+                println!(
+                    "{}{} {:03X}: db {}",
+                    marker,
+                    breakpoint,
+                    address,
+                    bytes.join(", ")
+                );
+                address = address.wrapping_add(2);
+            }
+        }
+        if address == 0 {
+            break;
+        }
+    }
+}
+
+fn print_screen(debugger: &ProgramDebugger) {
+    let emulator = debugger.emulator();
+    let (width, height) = emulator.screen_size();
+    for y in 0..height {
+        let row: String = (0..width)
+            .map(|x| match emulator.output().get(x, y) {
+                0 => '.',
+                _ => '#',
+            })
+            .collect();
+        println!("{}", row);
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use chip_8::disassembler::Disassembly;
+use chip_8::emulator::emulator::PC_START;
+use chip_8::emulator::instruction::Instruction;
+
+/// Disassemble a CHIP-8 program, printing the address, raw bytes and mnemonic of every instruction.
+#[derive(StructOpt)]
+struct Opt {
+    /// The program to disassemble
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Only disassemble code that can be reached from the entry point,
+    /// and print it as assembly with labels instead of a listing
+    #[structopt(long)]
+    recursive: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let program = std::fs::read(&opt.input)?;
+
+    if opt.recursive {
+        print!("{}", Disassembly::new(&program));
+        return Ok(());
+    }
+
+    // Disassemble linearly, writing anything that doesn't decode as data
+    let mut offset = 0;
+    while offset < program.len() {
+        let bytes = &program[offset..];
+        let (len, text) = match Instruction::decode(bytes) {
+            Ok(instruction) => (instruction.size() as usize, instruction.to_string()),
+            Err(_) => {
+                let len = std::cmp::min(2, bytes.len());
+                let data: Vec<_> = bytes[..len].iter().map(|b| format!("{:#04X}", b)).collect();
+                (len, format!("db {}", data.join(", ")))
+            }
+        };
+        let raw: Vec<_> = bytes[..len]
+            .chunks(2)
+            .map(|word| {
+                word.iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<String>()
+            })
+            .collect();
+        println!(
+            "{:03X}: {:<10} {}",
+            PC_START as usize + offset,
+            raw.join(" "),
+            text
+        );
+        offset += len;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use chip_8::debugger::Debugger;
+use chip_8::emulator::input::DummyInput;
+use chip_8::emulator::output::DummyOutput;
+use chip_8::emulator::quirks::Quirks;
+use chip_8::emulator::Emulator;
+use chip_8::gdb::GdbStub;
+
+/// Serve a CHIP-8 program to a debugger such as GDB, using the GDB remote serial protocol.
+#[derive(StructOpt)]
+struct Opt {
+    /// The program to debug
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// The quirks preset to run the program with (vip, chip48, schip, schip-legacy or xochip)
+    #[structopt(long)]
+    quirks: Option<Quirks>,
+
+    /// The number of instructions to execute per frame, at 60 frames per second
+    #[structopt(long, default_value = "10")]
+    cycles_per_frame: usize,
+
+    /// The local port to listen for the debugger on
+    #[structopt(long, default_value = "1234")]
+    port: u16,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let program = std::fs::read(&opt.input)?;
+    let quirks = opt.quirks.unwrap_or_default();
+    let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+    emulator.load(&program);
+    let mut stub = GdbStub::new(Debugger::new(emulator, opt.cycles_per_frame));
+
+    let listener = TcpListener::bind(("127.0.0.1", opt.port))?;
+    println!("Waiting for a debugger on {}", listener.local_addr()?);
+    let (stream, address) = listener.accept()?;
+    log::info!("Debugger connected from {}", address);
+    stub.serve(stream)?;
+    log::info!("Debugger disconnected");
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use chip_8::octo::compile;
+
+/// Compile an Octo program to a CHIP-8 program.
+#[derive(StructOpt)]
+struct Opt {
+    /// The Octo source
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Where to write the program, the source with a .ch8 extension by default
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let source = std::fs::read_to_string(&opt.input)?;
+
+    let program = match compile(&source) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}:{}", opt.input.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let output = match opt.output {
+        Some(output) => output,
+        None => opt.input.with_extension("ch8"),
+    };
+    log::info!("Writing {:?}", output);
+    std::fs::write(output, program.to_bytes())?;
+
+    Ok(())
+}
@@ -1,14 +1,21 @@
-use chip_8::emulator::{input::EmulatorInput, output::EmulatorOutput};
+use chip_8::emulator::audio::EmulatorAudio;
+use chip_8::emulator::input::{EmulatorInput, KeyEvent};
+use chip_8::emulator::output::EmulatorOutput;
 
 use super::key_manager::KeyManager;
 
-use crossterm::event::KeyCode;
+use crossterm::event::{
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, ResetColor, SetForegroundColor};
 use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{cursor, execute};
 use std::io::{stdout, Write};
 
 const SCREEN_WIDTH: usize = 128;
 const SCREEN_HEIGHT: usize = 64;
+/// The colors of pixels set in the first plane, the second plane, and both planes.
+const PLANE_COLORS: [Color; 3] = [Color::White, Color::DarkYellow, Color::DarkGrey];
 
 pub struct CrosstermInput<'a> {
     key_manager: &'a KeyManager,
@@ -21,32 +28,56 @@ impl CrosstermInput<'_> {
 }
 
 impl EmulatorInput for CrosstermInput<'_> {
-    fn get_key(&self) -> Option<u8> {
-        let key = self.key_manager.get_key()?;
-        key_to_u8(key)
+    fn pressed_keys(&self) -> u16 {
+        self.key_manager.pressed_keys()
     }
 
-    fn get_key_blocking(&self) -> u8 {
-        loop {
-            let key = self.key_manager.get_key_blocking();
-            if let Some(i) = key_to_u8(key) {
-                return i;
-            }
-        }
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        self.key_manager.poll_key_event()
     }
 }
 
 pub struct CrosstermOutput {
     cells: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    width: usize,
+    height: usize,
+    /// Whether the terminal has been asked to report key releases
+    keyboard_enhanced: bool,
 }
 
 impl CrosstermOutput {
+    /// Set up the terminal, which must be done before anything starts reading events,
+    /// since checking whether the terminal can report key releases reads its answer from them.
     pub fn new() -> CrosstermOutput {
         execute!(stdout(), EnterAlternateScreen);
         execute!(stdout(), cursor::Hide);
         terminal::enable_raw_mode();
-        let bottom = SCREEN_HEIGHT + 2;
-        let right = SCREEN_WIDTH + 2;
+        // The alternate screen has its own keyboard enhancements, so they are set after entering it.
+        // They are only popped again if pushing them succeeded.
+        let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+            | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+        let keyboard_enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(stdout(), PushKeyboardEnhancementFlags(flags)).is_ok();
+        let mut output = CrosstermOutput {
+            cells: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            width: SCREEN_WIDTH / 2,
+            height: SCREEN_HEIGHT / 2,
+            keyboard_enhanced,
+        };
+        output.draw_border();
+        output
+    }
+
+    /// The number of terminal columns used for a single pixel.
+    /// Low resolution pixels are twice as wide, so that both resolutions fill the same width.
+    fn pixel_width(&self) -> usize {
+        SCREEN_WIDTH / self.width
+    }
+
+    fn draw_border(&mut self) {
+        execute!(stdout(), ResetColor);
+        let bottom = self.height + 2;
+        let right = self.pixel_width() * self.width + 2;
         for y in 1..=bottom {
             for x in 1..=right {
                 if y == 1 || y == bottom || x == 1 || x == right {
@@ -70,19 +101,32 @@ impl CrosstermOutput {
                 }
             }
         }
-        CrosstermOutput {
-            cells: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
-        }
     }
 
     fn draw(&mut self, x: usize, y: usize, state: u8) {
-        execute!(stdout(), cursor::MoveTo(2 * x as u16 + 2, y as u16 + 2));
-        write!(stdout(), "{}", if state == 1 { "██" } else { "  " }).unwrap();
+        let pixel_width = self.pixel_width();
+        execute!(
+            stdout(),
+            cursor::MoveTo((pixel_width * x) as u16 + 2, y as u16 + 2)
+        );
+        let c = match state {
+            0 => " ",
+            state => {
+                let color = PLANE_COLORS[(state as usize - 1) % PLANE_COLORS.len()];
+                execute!(stdout(), SetForegroundColor(color));
+                "█"
+            }
+        };
+        write!(stdout(), "{}", c.repeat(pixel_width)).unwrap();
     }
 }
 
 impl Drop for CrosstermOutput {
     fn drop(&mut self) {
+        if self.keyboard_enhanced {
+            // The terminal is being restored as far as possible, even if this fails
+            let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+        }
         terminal::disable_raw_mode();
         execute!(stdout(), LeaveAlternateScreen);
         execute!(stdout(), cursor::Show);
@@ -106,23 +150,44 @@ impl EmulatorOutput for CrosstermOutput {
     fn clear(&mut self) {
         self.cells = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
         execute!(stdout(), Clear(ClearType::All));
+        self.draw_border();
         stdout().flush();
     }
 
     fn refresh(&mut self) {
         execute!(stdout(), cursor::MoveTo(1, 1));
-        for y in 0..SCREEN_HEIGHT {
-            for x in 0..SCREEN_WIDTH {
+        for y in 0..self.height {
+            for x in 0..self.width {
                 self.draw(x, y, self.cells[y][x]);
             }
         }
         stdout().flush();
     }
+
+    fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.clear();
+    }
+}
+
+/// Rings the terminal bell whenever the buzzer starts sounding.
+pub struct TerminalBell {
+    on: bool,
+}
+
+impl TerminalBell {
+    pub fn new() -> TerminalBell {
+        TerminalBell { on: false }
+    }
 }
 
-fn key_to_u8(key: KeyCode) -> Option<u8> {
-    match key {
-        KeyCode::Char(c) => c.to_digit(10).filter(|c| *c <= 0xF).map(|c| c as u8),
-        _ => None,
+impl EmulatorAudio for TerminalBell {
+    fn set_buzzer(&mut self, on: bool) {
+        if on && !self.on {
+            write!(stdout(), "\x07").unwrap();
+            stdout().flush();
+        }
+        self.on = on;
     }
 }
@@ -0,0 +1,190 @@
+use chip_8::emulator::input::KeyEvent;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a key counts as held after it is first pressed, if the terminal doesn't report releases.
+/// This has to outlast the delay before a held key starts repeating, which is 660 ms by default
+/// on X11 and 500 ms on most other systems, or a key would flicker up before its first repeat.
+const REPEAT_DELAY: Duration = Duration::from_millis(700);
+
+/// How many repeat intervals a held key can go without repeating before it counts as released.
+const MISSED_REPEATS: u32 = 2;
+
+/// Keeps track of which of the keys 0..=0xF are held down.
+///
+/// Terminals that support it report when keys are released, but most only report key presses,
+/// which repeat while the key is held. Until a release has been reported,
+/// a key counts as held until it stops repeating, judging by how often it has repeated so far.
+pub struct HeldKeys {
+    reports_releases: bool,
+    keys: [Option<Held>; 16],
+    events: VecDeque<KeyEvent>,
+}
+
+#[derive(Clone, Copy)]
+struct Held {
+    last_press: Instant,
+    repeat_interval: Option<Duration>,
+}
+
+impl Held {
+    fn deadline(&self) -> Instant {
+        let timeout = self
+            .repeat_interval
+            .map_or(REPEAT_DELAY, |interval| interval * MISSED_REPEATS);
+        self.last_press + std::cmp::min(timeout, REPEAT_DELAY)
+    }
+}
+
+impl HeldKeys {
+    pub fn new() -> HeldKeys {
+        HeldKeys {
+            reports_releases: false,
+            keys: [None; 16],
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Release the keys that seem to have stopped repeating.
+    fn expire(&mut self, now: Instant) {
+        if self.reports_releases {
+            return;
+        }
+        for key in 0..16 {
+            if matches!(self.keys[key], Some(held) if held.deadline() <= now) {
+                self.release(key as u8);
+            }
+        }
+    }
+
+    /// Handle a key being pressed, or repeating while held.
+    pub fn press(&mut self, key: u8, now: Instant) {
+        self.expire(now);
+        if let Some(held) = &mut self.keys[key as usize] {
+            held.repeat_interval = Some(now - held.last_press);
+            held.last_press = now;
+        } else {
+            self.keys[key as usize] = Some(Held {
+                last_press: now,
+                repeat_interval: None,
+            });
+            self.events.push_back(KeyEvent::Pressed(key));
+        }
+    }
+
+    /// Handle a key release reported by the terminal,
+    /// after which releases are no longer estimated.
+    pub fn report_release(&mut self, key: u8) {
+        self.reports_releases = true;
+        self.release(key);
+    }
+
+    fn release(&mut self, key: u8) {
+        if self.keys[key as usize].take().is_some() {
+            self.events.push_back(KeyEvent::Released(key));
+        }
+    }
+
+    /// The held keys as a bitmask, with bit `n` set while key `n` is held.
+    pub fn pressed(&mut self, now: Instant) -> u16 {
+        self.expire(now);
+        self.keys
+            .iter()
+            .enumerate()
+            .filter(|(_, held)| held.is_some())
+            .fold(0, |keys, (key, _)| keys | 1 << key)
+    }
+
+    pub fn poll_event(&mut self, now: Instant) -> Option<KeyEvent> {
+        self.expire(now);
+        self.events.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn events(held_keys: &mut HeldKeys, now: Instant) -> Vec<KeyEvent> {
+        std::iter::from_fn(|| held_keys.poll_event(now)).collect()
+    }
+
+    #[test]
+    fn keys_are_held_until_the_repeat_delay() {
+        let start = Instant::now();
+        let mut held_keys = HeldKeys::new();
+        held_keys.press(2, start);
+        held_keys.press(8, start + REPEAT_DELAY / 2);
+
+        assert_eq!(held_keys.pressed(start + REPEAT_DELAY / 2), 1 << 2 | 1 << 8);
+        assert_eq!(held_keys.pressed(start + REPEAT_DELAY), 1 << 8);
+        assert_eq!(held_keys.pressed(start + REPEAT_DELAY * 2), 0);
+        assert_eq!(
+            events(&mut held_keys, start),
+            vec![
+                KeyEvent::Pressed(2),
+                KeyEvent::Pressed(8),
+                KeyEvent::Released(2),
+                KeyEvent::Released(8),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_are_held_through_the_x11_repeat_delay() {
+        let start = Instant::now();
+        let first_repeat = start + Duration::from_millis(660);
+        let mut held_keys = HeldKeys::new();
+        held_keys.press(3, start);
+
+        assert_eq!(held_keys.pressed(first_repeat), 1 << 3);
+        held_keys.press(3, first_repeat);
+        assert_eq!(held_keys.pressed(first_repeat), 1 << 3);
+        assert_eq!(
+            events(&mut held_keys, first_repeat),
+            vec![KeyEvent::Pressed(3)]
+        );
+    }
+
+    #[test]
+    fn repeating_keys_are_released_soon_after_they_stop() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(30);
+        let mut held_keys = HeldKeys::new();
+        let first_repeat = start + REPEAT_DELAY / 2;
+        held_keys.press(5, start);
+        held_keys.press(5, first_repeat);
+        held_keys.press(5, first_repeat + interval);
+
+        let last_press = first_repeat + interval;
+        assert_eq!(held_keys.pressed(last_press + interval), 1 << 5);
+        assert_eq!(held_keys.pressed(last_press + interval * MISSED_REPEATS), 0);
+        assert_eq!(
+            events(&mut held_keys, last_press),
+            vec![KeyEvent::Pressed(5), KeyEvent::Released(5)]
+        );
+    }
+
+    #[test]
+    fn reported_releases_replace_the_estimate() {
+        let start = Instant::now();
+        let mut held_keys = HeldKeys::new();
+        held_keys.press(1, start);
+        held_keys.report_release(1);
+        held_keys.press(1, start);
+
+        assert_eq!(held_keys.pressed(start + REPEAT_DELAY * 10), 1 << 1);
+        held_keys.report_release(1);
+        assert_eq!(held_keys.pressed(start + REPEAT_DELAY * 10), 0);
+        assert_eq!(
+            events(&mut held_keys, start),
+            vec![
+                KeyEvent::Pressed(1),
+                KeyEvent::Released(1),
+                KeyEvent::Pressed(1),
+                KeyEvent::Released(1),
+            ]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use crossterm::event::KeyCode;
 use std::{
     collections::VecDeque,
-    sync::{Condvar, Mutex},
+    sync::Mutex,
     time::{Duration, SystemTime},
 };
 
@@ -11,7 +11,6 @@ use std::{
 pub struct KeyBuffer {
     timeout: Duration,
     buffer: Mutex<VecDeque<(KeyCode, SystemTime)>>,
-    condvar: Condvar,
 }
 
 impl KeyBuffer {
@@ -20,7 +19,6 @@ impl KeyBuffer {
         KeyBuffer {
             timeout,
             buffer: Mutex::new(VecDeque::new()),
-            condvar: Condvar::new(),
         }
     }
 
@@ -42,36 +40,12 @@ impl KeyBuffer {
             .lock()
             .unwrap()
             .push_back((key_code, SystemTime::now()));
-        self.condvar.notify_one();
-    }
-
-    /// Peek at the current keypress
-    pub fn peek(&self) -> Option<KeyCode> {
-        self.clean();
-        // Select the keycode component
-        self.buffer.lock().unwrap().front().map(|(kc, _)| *kc)
     }
 
     /// Pop a keypress from the buffer if a fresh enough one exists.
     pub fn pop(&self) -> Option<KeyCode> {
-        let mut buffer_guard = self.buffer.lock().unwrap();
-        buffer_guard
-            .pop_front()
-            .filter(|(_, ts)| ts.elapsed().unwrap() < self.timeout)
-            .map(|(kc, _)| kc)
-    }
-
-    /// Pop a keypress from the buffer, even if it requires some waiting.
-    pub fn pop_blocking(&self) -> KeyCode {
-        let mut buffer_guard = self.buffer.lock().unwrap();
-        loop {
-            if let Some((key_code, timestamp)) = buffer_guard.pop_front() {
-                if timestamp.elapsed().unwrap() < self.timeout {
-                    return key_code;
-                }
-            }
-            buffer_guard = self.condvar.wait(buffer_guard).unwrap();
-        }
+        self.clean();
+        self.buffer.lock().unwrap().pop_front().map(|(kc, _)| kc)
     }
 }
 
@@ -79,25 +53,23 @@ impl KeyBuffer {
 mod tests {
 
     use super::*;
-    use std::{sync::Arc, thread};
+    use std::thread;
 
     #[test]
-    fn push_and_pop_blocking() {
-        let kb = Arc::new(KeyBuffer::new(Duration::from_millis(100)));
-        let kb_clone = kb.clone();
-
-        let input = KeyCode::Null;
-
-        let consumer = thread::spawn(move || kb.pop_blocking());
-
-        let producer = thread::spawn(move || {
-            thread::sleep(Duration::from_millis(10));
-            kb_clone.push(input)
-        });
+    fn push_and_pop() {
+        let kb = KeyBuffer::new(Duration::from_millis(100));
+        kb.push(KeyCode::Char('a'));
+        kb.push(KeyCode::Char('b'));
+        assert_eq!(kb.pop(), Some(KeyCode::Char('a')));
+        assert_eq!(kb.pop(), Some(KeyCode::Char('b')));
+        assert_eq!(kb.pop(), None);
+    }
 
-        // Allow the consumer to arrive first
-        let output = consumer.join().unwrap();
-        producer.join().unwrap();
-        assert_eq!(output, input);
+    #[test]
+    fn old_keypresses_are_dropped() {
+        let kb = KeyBuffer::new(Duration::from_millis(10));
+        kb.push(KeyCode::Null);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(kb.pop(), None);
     }
 }
@@ -1,39 +1,65 @@
+use super::held_keys::HeldKeys;
 use super::key_buffer::KeyBuffer;
-use crossterm::event::{read, Event, KeyCode};
+use super::keymap::Keymap;
+use chip_8::emulator::input::KeyEvent;
+use crossterm::event::{poll, read, Event, KeyCode, KeyEventKind};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub struct KeyManager {
     stop: Arc<AtomicBool>,
     key_buffer: Arc<KeyBuffer>,
+    held_keys: Arc<Mutex<HeldKeys>>,
+    /// Whether backspace is held, as key 0
+    held_backspace: Arc<Mutex<HeldKeys>>,
     event_listener: Option<JoinHandle<()>>,
 }
 
 /// A struct for managing keypresses that will automatically
 /// start a thread that grabs keypresses.
 impl KeyManager {
-    // Start even listener thread
-    pub fn new() -> KeyManager {
+    // Start even listener thread, which maps keys to CHIP-8 keys with `keymap`
+    pub fn new(keymap: Keymap) -> KeyManager {
         let stop = Arc::new(AtomicBool::new(false));
         let key_buffer = Arc::new(KeyBuffer::new(Duration::from_millis(250)));
-        let event_listener = event_listener(stop.clone(), key_buffer.clone());
+        let held_keys = Arc::new(Mutex::new(HeldKeys::new()));
+        let held_backspace = Arc::new(Mutex::new(HeldKeys::new()));
+        let event_listener = event_listener(
+            stop.clone(),
+            key_buffer.clone(),
+            held_keys.clone(),
+            held_backspace.clone(),
+            keymap,
+        );
         KeyManager {
             stop,
             key_buffer,
+            held_keys,
+            held_backspace,
             event_listener: Some(event_listener),
         }
     }
 
-    /// Get the currently pressed key if one exists
-    pub fn get_key(&self) -> Option<KeyCode> {
-        self.key_buffer.peek()
+    /// Take the currently pressed key if one exists, so that it is only handled once
+    pub fn pop_key(&self) -> Option<KeyCode> {
+        self.key_buffer.pop()
     }
 
-    /// Get a key by blocking
-    pub fn get_key_blocking(&self) -> KeyCode {
-        self.key_buffer.pop_blocking()
+    /// The CHIP-8 keys that are held down, as a bitmask
+    pub fn pressed_keys(&self) -> u16 {
+        self.held_keys.lock().unwrap().pressed(Instant::now())
+    }
+
+    /// Take the oldest CHIP-8 key press or release, if any
+    pub fn poll_key_event(&self) -> Option<KeyEvent> {
+        self.held_keys.lock().unwrap().poll_event(Instant::now())
+    }
+
+    /// Whether backspace is held down
+    pub fn backspace_held(&self) -> bool {
+        self.held_backspace.lock().unwrap().pressed(Instant::now()) != 0
     }
 }
 
@@ -48,20 +74,41 @@ impl Drop for KeyManager {
     }
 }
 
-/// Starts a thread that listens for key events and pushes them to the key buffer.
-fn event_listener(stop: Arc<AtomicBool>, key_buffer: Arc<KeyBuffer>) -> JoinHandle<()> {
+/// How often the event listener checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Starts a thread that listens for key events and pushes key presses to the key buffer,
+/// also keeping track of which CHIP-8 keys are held, and whether backspace is.
+fn event_listener(
+    stop: Arc<AtomicBool>,
+    key_buffer: Arc<KeyBuffer>,
+    held_keys: Arc<Mutex<HeldKeys>>,
+    held_backspace: Arc<Mutex<HeldKeys>>,
+    keymap: Keymap,
+) -> JoinHandle<()> {
     thread::spawn(move || {
-        loop {
+        while !stop.load(Ordering::Relaxed) {
+            if !poll(POLL_INTERVAL).unwrap() {
+                continue;
+            }
             let event = read().unwrap();
             log::info!("Got event {:?}", event);
 
-            // Check the shared data, and possibly stop
-            if stop.load(Ordering::Relaxed) {
-                break;
-            }
-
-            // Push keypresses into the key buffer
             if let Event::Key(key_event) = event {
+                let (held, key) = match key_event.code {
+                    KeyCode::Backspace => (&held_backspace, Some(0)),
+                    code => (&held_keys, keymap.get(code)),
+                };
+                // Releases are only reported by some terminals
+                if key_event.kind == KeyEventKind::Release {
+                    if let Some(key) = key {
+                        held.lock().unwrap().report_release(key);
+                    }
+                    continue;
+                }
+                if let Some(key) = key {
+                    held.lock().unwrap().press(key, Instant::now());
+                }
                 key_buffer.push(key_event.code);
             }
         }
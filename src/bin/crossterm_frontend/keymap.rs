@@ -0,0 +1,166 @@
+use crossterm::event::KeyCode;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// The default layout, where the left side of a QWERTY keyboard
+/// stands in for the hexadecimal keypad of the COSMAC VIP:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// q w e r  ->  4 5 6 D
+/// a s d f      7 8 9 E
+/// z x c v      A 0 B F
+/// ```
+const QWERTY: [(char, u8); 16] = [
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('4', 0xC),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('r', 0xD),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('f', 0xE),
+    ('z', 0xA),
+    ('x', 0x0),
+    ('c', 0xB),
+    ('v', 0xF),
+];
+
+/// Maps terminal keys to CHIP-8 keys.
+pub struct Keymap {
+    keys: HashMap<KeyCode, u8>,
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    /// A terminal key that can't be mapped.
+    UnknownKey(String),
+    /// A CHIP-8 key that is not an integer from 0x0 to 0xF.
+    InvalidValue(String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(e) => write!(f, "{}", e),
+            KeymapError::Toml(e) => write!(f, "{}", e),
+            KeymapError::UnknownKey(key) => write!(f, "unknown key {:?}", key),
+            KeymapError::InvalidValue(key) => {
+                write!(f, "{:?} must be mapped to a key from 0x0 to 0xF", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+impl Keymap {
+    pub fn qwerty() -> Keymap {
+        let keys = QWERTY
+            .iter()
+            .map(|&(c, key)| (KeyCode::Char(c), key))
+            .collect();
+        Keymap { keys }
+    }
+
+    /// The CHIP-8 key for a terminal key, if it is mapped to one.
+    pub fn get(&self, key: KeyCode) -> Option<u8> {
+        self.keys.get(&normalize(key)).copied()
+    }
+
+    /// Remap the keys in a TOML file, leaving the rest as they are.
+    pub fn load(&mut self, path: &Path) -> Result<(), KeymapError> {
+        let source = std::fs::read_to_string(path).map_err(KeymapError::Io)?;
+        self.remap(&source)
+    }
+
+    /// Remap keys from TOML such as `w = 0x5`, where the names on the left
+    /// are characters or one of space, enter, tab, up, down, left and right.
+    fn remap(&mut self, source: &str) -> Result<(), KeymapError> {
+        let table: toml::value::Table = toml::from_str(source).map_err(KeymapError::Toml)?;
+        for (name, value) in table {
+            let key = parse_key(&name).ok_or_else(|| KeymapError::UnknownKey(name.clone()))?;
+            let value = value
+                .as_integer()
+                .filter(|value| (0..=0xF).contains(value))
+                .ok_or(KeymapError::InvalidValue(name))?;
+            self.keys.insert(key, value as u8);
+        }
+        Ok(())
+    }
+}
+
+/// Letters are mapped regardless of case, so they work with caps lock on.
+fn normalize(key: KeyCode) -> KeyCode {
+    match key {
+        KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+        key => key,
+    }
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(normalize(KeyCode::Char(c)));
+    }
+    let key = match name.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn qwerty_layout() {
+        let keymap = Keymap::qwerty();
+        assert_eq!(keymap.get(KeyCode::Char('1')), Some(0x1));
+        assert_eq!(keymap.get(KeyCode::Char('4')), Some(0xC));
+        assert_eq!(keymap.get(KeyCode::Char('X')), Some(0x0));
+        assert_eq!(keymap.get(KeyCode::Char('0')), None);
+    }
+
+    #[test]
+    fn remap_overrides_some_keys() {
+        let mut keymap = Keymap::qwerty();
+        keymap.remap("w = 0x1\nup = 5\nspace = 0xF").unwrap();
+        assert_eq!(keymap.get(KeyCode::Char('w')), Some(0x1));
+        assert_eq!(keymap.get(KeyCode::Up), Some(0x5));
+        assert_eq!(keymap.get(KeyCode::Char(' ')), Some(0xF));
+        assert_eq!(keymap.get(KeyCode::Char('a')), Some(0x7));
+    }
+
+    #[test]
+    fn remap_rejects_invalid_keys() {
+        let mut keymap = Keymap::qwerty();
+        assert!(matches!(
+            keymap.remap("backspace = 1"),
+            Err(KeymapError::UnknownKey(_))
+        ));
+        assert!(matches!(
+            keymap.remap("w = 16"),
+            Err(KeymapError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            keymap.remap("w = \"a\""),
+            Err(KeymapError::InvalidValue(_))
+        ));
+    }
+}
@@ -1,16 +1,30 @@
-use std::path::PathBuf;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use structopt::StructOpt;
 
+use chip_8::emulator::audio::EmulatorAudio;
 use chip_8::emulator::emulator::Emulator;
+use chip_8::emulator::input::EmulatorInput;
+use chip_8::emulator::movie::{Movie, Recorder};
+use chip_8::emulator::output::EmulatorOutput;
+use chip_8::emulator::quirks::Quirks;
+use chip_8::emulator::rewind::Rewind;
+use chip_8::emulator::snapshot::Snapshot;
+use chip_8::emulator::trace::Tracer;
 
+mod held_keys;
 mod key_buffer;
 
 mod key_manager;
 use key_manager::KeyManager;
 
+mod keymap;
+use keymap::Keymap;
+
 mod crossterm_io;
-use crossterm_io::{CrosstermInput, CrosstermOutput};
+use crossterm_io::{CrosstermInput, CrosstermOutput, TerminalBell};
 
 use crossterm::event::KeyCode;
 
@@ -20,27 +34,214 @@ struct Opt {
     /// The program to execute
     #[structopt(parse(from_os_str))]
     input: PathBuf,
+
+    /// The quirks preset to run the program with (vip, chip48, schip, schip-legacy or xochip)
+    #[structopt(long)]
+    quirks: Option<Quirks>,
+
+    /// The number of instructions to execute per frame, at 60 frames per second
+    #[structopt(long, default_value = "10")]
+    cycles_per_frame: usize,
+
+    /// The memory in MiB to keep history in, for rewinding by holding backspace
+    #[structopt(long, default_value = "16")]
+    rewind_budget: usize,
+
+    /// Write a line for every executed instruction to a file
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// A TOML file that remaps keys, such as `w = 0x5`, on top of the default QWERTY layout
+    #[structopt(long, parse(from_os_str))]
+    keymap: Option<PathBuf>,
+
+    /// Record the keys held in every frame to a movie file, which `no_frontend --replay` can play back.
+    /// Rewinding and loading states are disabled while recording
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
 }
 
-fn main() -> std::io::Result<()> {
+/// The number of save state slots, saved to with F1-F4 and loaded from with F5-F8.
+const SAVE_SLOTS: u8 = 4;
+
+fn main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
 
     // Get configuration and read input file
     let opt = Opt::from_args();
     log::info!("Executing {:?}", &opt.input);
-    let program = std::fs::read(opt.input)?;
+    let program = std::fs::read(&opt.input)?;
+
+    // Set up the terminal before listening for keys
+    let keymap = keymap(&opt)?;
+    let output = CrosstermOutput::new();
+    let key_manager = KeyManager::new(keymap);
 
-    let key_manager = KeyManager::new();
+    // Only keep track of the keys held in every frame when recording
+    let quirks = opt.quirks.unwrap_or_default();
+    let seed = rand::random();
+    let input = CrosstermInput::new(&key_manager);
+    let result = match &opt.record {
+        Some(path) => {
+            let mut emulator = start(&program, Recorder::new(input), output, quirks, seed);
+            let result = run(&mut emulator, &opt, &key_manager);
 
-    // Load instructions into emulator memory
-    let mut emulator = Emulator::new(CrosstermInput::new(&key_manager), CrosstermOutput::new());
-    emulator.load(&program);
+            // Keep the recording even if the program faults
+            let frames = emulator.input().frames().to_vec();
+            drop(emulator);
+            log::info!("Writing {} frames to {:?}", frames.len(), path);
+            let movie = Movie {
+                frames,
+                ..Movie::new(&program, seed, quirks, opt.cycles_per_frame as u32)
+            };
+            std::fs::write(path, movie.to_bytes())?;
+            result
+        }
+        None => run(
+            &mut start(&program, input, output, quirks, seed),
+            &opt,
+            &key_manager,
+        ),
+    };
+
+    // Report any error after dropping the emulator has restored the terminal
+    if let Err(e) = &result {
+        log::error!("{}", e);
+    }
+    result
+}
+
+/// Load the program into an emulator with `input`, with its random number generator seeded with `seed`.
+fn start<I: EmulatorInput>(
+    program: &[u8],
+    input: I,
+    output: CrosstermOutput,
+    quirks: Quirks,
+    seed: u64,
+) -> Emulator<I, CrosstermOutput, TerminalBell> {
+    let mut emulator = Emulator::with_audio(input, output, TerminalBell::new(), quirks);
+    emulator.load(program);
+    emulator.seed_rng(seed);
+    emulator
+}
 
-    // Start execution
-    while key_manager.get_key() != Some(KeyCode::Char('q')) {
-        emulator.step();
-        std::thread::sleep(std::time::Duration::from_millis(1_000 / 120));
+/// Run the program at 60 frames per second until it exits or escape is pressed,
+/// handling save states and rewinding in between frames.
+fn run<I: EmulatorInput>(
+    emulator: &mut Emulator<I, CrosstermOutput, TerminalBell>,
+    opt: &Opt,
+    key_manager: &KeyManager,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &opt.trace {
+        log::info!("Tracing to {:?}", path);
+        let file = std::fs::File::create(path)?;
+        emulator.set_tracer(Some(Tracer::new(std::io::BufWriter::new(file))));
     }
 
+    // Rewinding and loading states would make the recording go out of sync
+    let recording = opt.record.is_some();
+    let frame_duration = Duration::from_secs(1) / 60;
+    let mut next_frame = Instant::now();
+    let mut rewind = Rewind::new(opt.rewind_budget << 20);
+    let result = 'run: loop {
+        if emulator.has_exited() {
+            break Ok(());
+        }
+        // Handle every key pressed since the last frame once,
+        // while the keys of the program are read from which ones are held
+        while let Some(key) = key_manager.pop_key() {
+            match key {
+                KeyCode::Esc => break 'run Ok(()),
+                KeyCode::F(n) if (1..=2 * SAVE_SLOTS).contains(&n) => {
+                    let (slot, result) = if n <= SAVE_SLOTS {
+                        (n, save_state(emulator, &opt.input, n))
+                    } else if recording {
+                        (
+                            n - SAVE_SLOTS,
+                            Err("can't load states while recording".into()),
+                        )
+                    } else {
+                        (
+                            n - SAVE_SLOTS,
+                            load_state(emulator, &opt.input, n - SAVE_SLOTS),
+                        )
+                    };
+                    if let Err(e) = result {
+                        log::warn!("Save state slot {}: {}", slot, e);
+                    }
+                }
+                // Step back a frame for every press, which repeats while backspace is held
+                KeyCode::Backspace if !recording => {
+                    if let Some(snapshot) = rewind.pop() {
+                        emulator.restore(&snapshot)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Hold still in between the repeats of backspace
+        if recording || !key_manager.backspace_held() {
+            if !recording {
+                rewind.push(&emulator.snapshot());
+            }
+            if let Err(e) = emulator.run_frame(opt.cycles_per_frame) {
+                break Err(e);
+            }
+        }
+        next_frame += frame_duration;
+        std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+    };
+
+    // Keep the trace up to the fault
+    if let Some(mut tracer) = emulator.set_tracer(None) {
+        tracer.flush()?;
+    }
+    Ok(result?)
+}
+
+/// The keymap given with `--keymap`,
+/// with the keys in `<program>.keys.toml` next to the program remapped on top if it exists.
+fn keymap(opt: &Opt) -> Result<Keymap, Box<dyn Error>> {
+    let mut keymap = Keymap::qwerty();
+    let program_keymap = opt.input.with_extension("keys.toml");
+    let paths = opt
+        .keymap
+        .iter()
+        .chain(Some(&program_keymap).filter(|p| p.exists()));
+    for path in paths {
+        log::info!("Loading keymap {:?}", path);
+        keymap
+            .load(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(keymap)
+}
+
+/// The file that a save state slot for a program is stored in, next to the program.
+fn state_path(program: &Path, slot: u8) -> PathBuf {
+    program.with_extension(format!("state{}", slot))
+}
+
+fn save_state<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio>(
+    emulator: &Emulator<I, O, A>,
+    program: &Path,
+    slot: u8,
+) -> Result<(), Box<dyn Error>> {
+    let path = state_path(program, slot);
+    log::info!("Saving state to {:?}", path);
+    std::fs::write(path, emulator.snapshot().to_bytes())?;
+    Ok(())
+}
+
+fn load_state<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio>(
+    emulator: &mut Emulator<I, O, A>,
+    program: &Path,
+    slot: u8,
+) -> Result<(), Box<dyn Error>> {
+    let path = state_path(program, slot);
+    log::info!("Loading state from {:?}", path);
+    let snapshot = Snapshot::from_bytes(&std::fs::read(path)?)?;
+    emulator.restore(&snapshot)?;
     Ok(())
 }
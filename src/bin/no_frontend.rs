@@ -1,9 +1,22 @@
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use structopt::StructOpt;
 
 use chip_8::emulator::Emulator;
-use chip_8::emulator::{input::DummyInput, output::DummyOutput};
+use chip_8::emulator::{
+    audio::{EmulatorAudio, PcmAudio},
+    input::{DummyInput, EmulatorInput},
+    movie::{Movie, Replay},
+    output::DummyOutput,
+    quirks::Quirks,
+    trace::Tracer,
+};
+
+/// The sample rate of recorded audio.
+const SAMPLE_RATE: u32 = 44100;
 
 /// The program options.
 #[derive(StructOpt)]
@@ -11,23 +24,139 @@ struct Opt {
     /// The program to execute
     #[structopt(parse(from_os_str))]
     input: PathBuf,
+
+    /// The quirks preset to run the program with (vip, chip48, schip, schip-legacy or xochip)
+    #[structopt(long)]
+    quirks: Option<Quirks>,
+
+    /// The number of instructions to execute per frame, at 60 frames per second
+    #[structopt(long, default_value = "10")]
+    cycles_per_frame: usize,
+
+    /// Record the sound of the program to a WAV file
+    #[structopt(long, parse(from_os_str))]
+    wav: Option<PathBuf>,
+
+    /// Write a line for every executed instruction to a file
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Replay the keys recorded in a movie file as fast as possible, stopping after its last frame.
+    /// The quirks and cycles per frame are taken from the movie
+    #[structopt(long, parse(from_os_str))]
+    replay: Option<PathBuf>,
 }
 
-fn main() -> std::io::Result<()> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     pretty_env_logger::init();
 
     // Get configuration and read input file
     let opt = Opt::from_args();
     log::info!("Executing {:?}", &opt.input);
-    let program = std::fs::read(opt.input)?;
+    let program = std::fs::read(&opt.input)?;
+
+    match &opt.replay {
+        Some(path) => {
+            let movie = Movie::from_bytes(&std::fs::read(path)?)?;
+            if !movie.matches_program(&program) {
+                return Err(format!("{:?} was recorded with another program", path).into());
+            }
+            log::info!("Replaying {} frames from {:?}", movie.frames.len(), path);
+            let input = Replay::new(movie.frames.clone());
+            start(&opt, &program, input, Some(&movie))
+        }
+        None => start(&opt, &program, DummyInput, None),
+    }
+}
+
+/// Load the program into an emulator with `input`, set up like `movie` if replaying one, and run it.
+fn start<I: EmulatorInput>(
+    opt: &Opt,
+    program: &[u8],
+    input: I,
+    movie: Option<&Movie>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (quirks, cycles_per_frame) = match movie {
+        Some(movie) => (movie.quirks, movie.cycles_per_frame as usize),
+        None => (opt.quirks.unwrap_or_default(), opt.cycles_per_frame),
+    };
+    let frames = movie.map(|movie| movie.frames.len());
 
     // Load instructions into emulator memory
-    let mut emulator = Emulator::dummy();
-    emulator.load(&program);
+    match &opt.wav {
+        Some(path) => {
+            let audio = PcmAudio::new(SAMPLE_RATE);
+            let mut emulator = Emulator::with_audio(input, DummyOutput::new(), audio, quirks);
+            emulator.load(program);
+            if let Some(movie) = movie {
+                emulator.seed_rng(movie.seed);
+            }
+
+            // Keep the recording even if the program faults
+            let result = run(
+                &mut emulator,
+                cycles_per_frame,
+                frames,
+                opt.trace.as_deref(),
+            );
+            log::info!("Writing audio to {:?}", path);
+            emulator
+                .audio()
+                .write_wav(BufWriter::new(File::create(path)?))?;
+            result?;
+        }
+        None => {
+            let mut emulator = Emulator::with_quirks(input, DummyOutput::new(), quirks);
+            emulator.load(program);
+            if let Some(movie) = movie {
+                emulator.seed_rng(movie.seed);
+            }
+            run(
+                &mut emulator,
+                cycles_per_frame,
+                frames,
+                opt.trace.as_deref(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the program at 60 frames per second until it exits,
+/// or as fast as possible for a number of `frames` if given,
+/// writing every executed instruction to `trace` if given.
+fn run<I: EmulatorInput, A: EmulatorAudio>(
+    emulator: &mut Emulator<I, DummyOutput, A>,
+    cycles_per_frame: usize,
+    frames: Option<usize>,
+    trace: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(path) = trace {
+        log::info!("Tracing to {:?}", path);
+        let tracer = Tracer::new(BufWriter::new(File::create(path)?));
+        emulator.set_tracer(Some(tracer));
+    }
+
+    let frame_duration = Duration::from_secs(1) / 60;
+    let mut next_frame = Instant::now();
+    let mut result = Ok(());
+    let mut frame = 0;
+    while !emulator.has_exited() && frames != Some(frame) {
+        if let Err(e) = emulator.run_frame(cycles_per_frame) {
+            result = Err(e);
+            break;
+        }
+        frame += 1;
+        if frames.is_none() {
+            next_frame += frame_duration;
+            std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+    }
 
-    // Start execution
-    loop {
-        emulator.step();
-        std::thread::sleep(std::time::Duration::from_millis(1_000 / 60));
+    // Keep the trace up to the fault
+    if let Some(mut tracer) = emulator.set_tracer(None) {
+        tracer.flush()?;
     }
+    Ok(result?)
 }
@@ -0,0 +1,353 @@
+use crate::emulator::audio::{DummyAudio, EmulatorAudio};
+use crate::emulator::fault::Fault;
+use crate::emulator::input::EmulatorInput;
+use crate::emulator::instruction::Instruction;
+use crate::emulator::memory_access::MemoryAccess;
+use crate::emulator::output::EmulatorOutput;
+use crate::emulator::Emulator;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Why the debugger stopped executing.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Pause {
+    /// Every requested instruction was executed, or the stepped over subroutine returned
+    Done,
+    /// The program counter reached a breakpoint at this address
+    Breakpoint(u16),
+    /// The program exited
+    Exited,
+    /// A watchpoint was triggered by the instruction at `pc`
+    Watchpoint { watchpoint: Watchpoint, pc: u16 },
+}
+
+/// A condition that pauses execution right after the instruction that causes it.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Watchpoint {
+    /// The byte at the address is read, such as by `Draw` or `RegLoad`
+    Read(u16),
+    /// The byte at the address is written, such as by `RegDump` or `SetIToBcdOfReg`
+    Write(u16),
+    /// The register is changed to the value
+    Register { register: u8, value: u8 },
+    /// I is changed to point into the range from `start` up to but not including `end`
+    I { start: u16, end: u16 },
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Watchpoint::Read(address) => write!(f, "read of {:#05X}", address),
+            Watchpoint::Write(address) => write!(f, "write to {:#05X}", address),
+            Watchpoint::Register { register, value } => {
+                write!(f, "V{:X} == {:#04X}", register, value)
+            }
+            Watchpoint::I { start, end } => write!(f, "I in {:#05X}..{:#05X}", start, end),
+        }
+    }
+}
+
+impl Watchpoint {
+    /// Whether the watchpoint is triggered by an instruction,
+    /// given the registers and I from before it was executed.
+    fn is_triggered<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio>(
+        &self,
+        emulator: &Emulator<I, O, A>,
+        registers: &[u8],
+        i: u16,
+    ) -> bool {
+        match *self {
+            Watchpoint::Read(address) => emulator.memory_accesses().iter().any(|access| {
+                matches!(access, MemoryAccess::Read { .. }) && access.address() == address
+            }),
+            Watchpoint::Write(address) => emulator.memory_accesses().iter().any(|access| {
+                matches!(access, MemoryAccess::Write { .. }) && access.address() == address
+            }),
+            Watchpoint::Register { register, value } => {
+                let x = register as usize & 0xF;
+                registers[x] != value && emulator.registers()[x] == value
+            }
+            Watchpoint::I { start, end } => {
+                let new_i = emulator.i();
+                new_i != i && (start..end).contains(&new_i)
+            }
+        }
+    }
+}
+
+/// An emulator that can be paused, stepped through and stopped at breakpoints and watchpoints.
+///
+/// The debugger counts the instructions executed in each frame,
+/// so the timers count down at the same rate whether the program is stepped through or run.
+pub struct Debugger<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio = DummyAudio> {
+    emulator: Emulator<I, O, A>,
+    cycles_per_frame: usize,
+    /// The instructions executed so far in the current frame
+    cycles: usize,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio> Debugger<I, O, A> {
+    /// Debug an emulator that executes `cycles_per_frame` instructions per frame.
+    pub fn new(emulator: Emulator<I, O, A>, cycles_per_frame: usize) -> Self {
+        Debugger {
+            emulator,
+            cycles_per_frame,
+            cycles: 0,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn emulator(&self) -> &Emulator<I, O, A> {
+        &self.emulator
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator<I, O, A> {
+        &mut self.emulator
+    }
+
+    pub fn into_emulator(self) -> Emulator<I, O, A> {
+        self.emulator
+    }
+
+    /// Stop before executing the instruction at `address`.
+    /// Returns false if there already was a breakpoint there.
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Returns false if there was no breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// The addresses with breakpoints, in order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Pause after any instruction that triggers the watchpoint.
+    /// Returns false if the watchpoint was already added.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    /// Returns false if the watchpoint was never added.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    /// The watchpoints, in the order they were added.
+    pub fn watchpoints(&self) -> impl Iterator<Item = Watchpoint> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    /// The instruction in memory at `address`, if it decodes to one.
+    pub fn instruction_at(&self, address: u16) -> Option<Instruction> {
+        let memory = self.emulator.memory();
+        let start = address as usize;
+        let end = std::cmp::min(start + 4, memory.len());
+        Instruction::decode(memory.get(start..end)?).ok()
+    }
+
+    /// Execute a single instruction, ignoring breakpoints.
+    /// Watchpoints are still reported, since they are triggered by the instruction.
+    pub fn step(&mut self) -> Result<Pause, Fault> {
+        self.run_until(1, |_| false)
+    }
+
+    /// Execute a single instruction, or a whole subroutine if the instruction is a call.
+    /// Stops early at breakpoints inside the subroutine,
+    /// or after `max_cycles` instructions, in case it never returns.
+    pub fn step_over(&mut self, max_cycles: usize) -> Result<Pause, Fault> {
+        let pc = self.emulator.program_counter();
+        match self.instruction_at(pc) {
+            Some(call @ Instruction::Call(_)) => {
+                let return_address = pc.wrapping_add(call.size());
+                let depth = self.emulator.stack().len();
+                self.run_until(max_cycles, |emulator| {
+                    emulator.program_counter() == return_address && emulator.stack().len() == depth
+                })
+            }
+            _ => self.step(),
+        }
+    }
+
+    /// Run until a breakpoint is reached, the program exits,
+    /// or `max_cycles` instructions have been executed.
+    /// A breakpoint at the current instruction is passed over, so execution can be resumed from it.
+    pub fn run(&mut self, max_cycles: usize) -> Result<Pause, Fault> {
+        self.run_until(max_cycles, |_| false)
+    }
+
+    /// Run the rest of the current frame, unless a breakpoint is reached first.
+    /// Call this 60 times per second to run the program at full speed.
+    pub fn run_frame(&mut self) -> Result<Pause, Fault> {
+        let remaining = self.cycles_per_frame.saturating_sub(self.cycles).max(1);
+        self.run_until(remaining, |_| false)
+    }
+
+    fn run_until(
+        &mut self,
+        max_cycles: usize,
+        done: impl Fn(&Emulator<I, O, A>) -> bool,
+    ) -> Result<Pause, Fault> {
+        for cycle in 0..max_cycles {
+            if self.emulator.has_exited() {
+                return Ok(Pause::Exited);
+            }
+            let pc = self.emulator.program_counter();
+            if cycle > 0 && self.breakpoints.contains(&pc) {
+                return Ok(Pause::Breakpoint(pc));
+            }
+
+            let registers = *self.emulator.registers();
+            let i = self.emulator.i();
+            self.emulator.step()?;
+            self.cycles += 1;
+            if self.cycles >= self.cycles_per_frame || self.emulator.is_waiting_for_vblank() {
+                self.emulator.end_frame();
+                self.cycles = 0;
+            }
+
+            let emulator = &self.emulator;
+            let triggered = self
+                .watchpoints
+                .iter()
+                .find(|watchpoint| watchpoint.is_triggered(emulator, &registers, i));
+            if let Some(watchpoint) = triggered {
+                return Ok(Pause::Watchpoint {
+                    watchpoint: *watchpoint,
+                    pc,
+                });
+            }
+
+            if done(&self.emulator) {
+                break;
+            }
+        }
+        if self.emulator.has_exited() {
+            return Ok(Pause::Exited);
+        }
+        Ok(Pause::Done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::emulator::input::DummyInput;
+    use crate::emulator::output::DummyOutput;
+
+    /// A program that calls a subroutine that counts V0 up, in a loop.
+    fn debugger() -> Debugger<DummyInput, DummyOutput> {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[
+            0x22, 0x04, // 200: CALL 0x204
+            0x12, 0x00, // 202: JP 0x200
+            0x70, 0x01, // 204: ADD V0, 0x01
+            0x00, 0xEE, // 206: RET
+        ]);
+        Debugger::new(emulator, 10)
+    }
+
+    #[test]
+    fn breakpoints_stop_execution() {
+        let mut debugger = debugger();
+        assert!(debugger.add_breakpoint(0x206));
+        assert!(!debugger.add_breakpoint(0x206));
+        assert_eq!(debugger.run(100), Ok(Pause::Breakpoint(0x206)));
+        assert_eq!(debugger.emulator().registers()[0], 1);
+
+        // Resuming passes over the breakpoint it stopped at
+        assert_eq!(debugger.run(100), Ok(Pause::Breakpoint(0x206)));
+        assert_eq!(debugger.emulator().registers()[0], 2);
+
+        assert!(debugger.remove_breakpoint(0x206));
+        assert_eq!(debugger.run(100), Ok(Pause::Done));
+    }
+
+    #[test]
+    fn step_over_runs_whole_subroutine() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step_over(100), Ok(Pause::Done));
+        assert_eq!(debugger.emulator().program_counter(), 0x202);
+        assert_eq!(debugger.emulator().registers()[0], 1);
+        assert!(debugger.emulator().stack().is_empty());
+
+        // Other instructions are stepped as usual
+        assert_eq!(debugger.step_over(100), Ok(Pause::Done));
+        assert_eq!(debugger.emulator().program_counter(), 0x200);
+    }
+
+    #[test]
+    fn stepping_counts_timers_down_once_per_frame() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[
+            0x60, 0x05, // LD V0, 0x05
+            0xF0, 0x15, // LD DT, V0
+            0x12, 0x04, // JP 0x204
+        ]);
+        let mut debugger = Debugger::new(emulator, 4);
+        for _ in 0..4 {
+            debugger.step().unwrap();
+        }
+        assert_eq!(debugger.emulator().timers(), (4, 0));
+        debugger.run(8).unwrap();
+        assert_eq!(debugger.emulator().timers(), (2, 0));
+    }
+
+    #[test]
+    fn watchpoints_pause_after_the_instruction() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[
+            0xA3, 0x00, // 200: LD I, 0x300
+            0x60, 0x7B, // 202: LD V0, 0x7B
+            0xF0, 0x33, // 204: LD B, V0
+            0xF0, 0x65, // 206: LD V0, [I]
+            0x70, 0x02, // 208: ADD V0, 0x02
+            0x12, 0x08, // 20A: JP 0x208
+        ]);
+        let mut debugger = Debugger::new(emulator, 10);
+        let watchpoints = [
+            Watchpoint::I {
+                start: 0x300,
+                end: 0x310,
+            },
+            Watchpoint::Write(0x302),
+            Watchpoint::Read(0x300),
+            Watchpoint::Register {
+                register: 0,
+                value: 0x0B,
+            },
+        ];
+        for watchpoint in watchpoints {
+            assert!(debugger.add_watchpoint(watchpoint));
+        }
+        assert!(!debugger.add_watchpoint(watchpoints[0]));
+
+        for (watchpoint, pc) in watchpoints.iter().zip([0x200, 0x204, 0x206, 0x208]) {
+            let pause = Pause::Watchpoint {
+                watchpoint: *watchpoint,
+                pc,
+            };
+            assert_eq!(debugger.run(100), Ok(pause));
+        }
+        assert_eq!(debugger.emulator().registers()[0], 0x0B);
+
+        // V0 wraps around to 0x0B again after 128 additions
+        assert!(debugger.remove_watchpoint(watchpoints[0]));
+        assert_eq!(debugger.watchpoints().count(), 3);
+        assert_eq!(debugger.run(255), Ok(Pause::Done));
+        assert!(matches!(debugger.run(2), Ok(Pause::Watchpoint { .. })));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::emulator::emulator::PC_START;
+use crate::emulator::instruction::{Addr, Const, Instruction};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// The most data bytes written on a single line.
+const BYTES_PER_LINE: usize = 8;
+
+/// What an address is referred to as, from least to most specific.
+/// An address referred to in several ways is labelled by the most specific one.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+enum Reference {
+    /// I is set to the address
+    Data,
+    /// I is set to the address, and a sprite is drawn from it
+    Sprite,
+    /// The address is jumped to
+    Location,
+    /// The address is called
+    Subroutine,
+}
+
+impl Reference {
+    fn prefix(self) -> &'static str {
+        match self {
+            Reference::Data => "data",
+            Reference::Sprite => "sprite",
+            Reference::Location => "loc",
+            Reference::Subroutine => "sub",
+        }
+    }
+}
+
+/// A disassembly of a program that only treats bytes as code if they can be executed.
+///
+/// Starting at the entry point, execution is followed through jumps, calls, skips and returns,
+/// and anything that is never reached is data.
+/// Data that I points at when a sprite is drawn is marked as sprite data.
+///
+/// Displaying a disassembly gives assembly with generated labels,
+/// such as `sub_2A4` and `sprite_300`, which assembles to the original program.
+pub struct Disassembly {
+    program: Vec<u8>,
+    /// The reachable instructions, by address
+    code: BTreeMap<u16, Instruction>,
+    /// The addresses of bytes drawn as sprites
+    sprites: HashSet<u16>,
+    /// The addresses referred to by instructions
+    references: BTreeMap<u16, Reference>,
+}
+
+/// A line of the disassembly, starting at an address.
+#[derive(Copy, Clone)]
+enum Item<'a> {
+    Code(Instruction),
+    Data(&'a [u8]),
+}
+
+impl Disassembly {
+    /// Disassemble a program that is loaded at `PC_START`.
+    pub fn new(program: &[u8]) -> Disassembly {
+        let max_len = 0x10000 - PC_START as usize;
+        let mut disassembly = Disassembly {
+            program: program[..std::cmp::min(program.len(), max_len)].to_vec(),
+            code: BTreeMap::new(),
+            sprites: HashSet::new(),
+            references: BTreeMap::new(),
+        };
+        disassembly.analyze();
+        disassembly
+    }
+
+    /// The instruction at an address, if it is reachable.
+    pub fn instruction_at(&self, address: u16) -> Option<Instruction> {
+        self.code.get(&address).copied()
+    }
+
+    /// Whether the byte at an address is drawn as part of a sprite.
+    pub fn is_sprite(&self, address: u16) -> bool {
+        self.sprites.contains(&address)
+    }
+
+    fn end(&self) -> usize {
+        PC_START as usize + self.program.len()
+    }
+
+    fn decode_at(&self, address: u16) -> Option<Instruction> {
+        let offset = address.checked_sub(PC_START)? as usize;
+        Instruction::decode(self.program.get(offset..)?).ok()
+    }
+
+    fn refer(&mut self, address: u16, reference: Reference) {
+        let entry = self.references.entry(address).or_insert(reference);
+        *entry = std::cmp::max(*entry, reference);
+    }
+
+    /// Follow every path of execution from the entry point,
+    /// along with the address in I if it is known.
+    fn analyze(&mut self) {
+        let mut visited = HashSet::new();
+        let mut pending = vec![(PC_START, None)];
+        while let Some((address, i)) = pending.pop() {
+            if !visited.insert((address, i)) {
+                continue;
+            }
+            let instruction = match self.decode_at(address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            self.code.insert(address, instruction);
+
+            let next = address.wrapping_add(instruction.size());
+            let mut next_i = i;
+            match instruction {
+                Instruction::Goto(Addr(target)) => {
+                    self.refer(target, Reference::Location);
+                    pending.push((target, i));
+                    continue;
+                }
+                // The subroutine may change I before returning
+                Instruction::Call(Addr(target)) => {
+                    self.refer(target, Reference::Subroutine);
+                    pending.push((target, i));
+                    next_i = None;
+                }
+                // The target of a jump table depends on V0, so it can't be followed
+                Instruction::SetPcToV0PlusAddr(Addr(target)) => {
+                    self.refer(target, Reference::Location);
+                    continue;
+                }
+                Instruction::Return | Instruction::Exit => continue,
+                Instruction::IfRegEqConst(..)
+                | Instruction::IfRegNeqConst(..)
+                | Instruction::IfRegEqReg(..)
+                | Instruction::IfRegNeqReg(..)
+                | Instruction::IfKeyEqVx(..)
+                | Instruction::IfKeyNeqVx(..) => {
+                    let skipped = self.decode_at(next).map_or(2, |skipped| skipped.size());
+                    pending.push((next.wrapping_add(skipped), i));
+                }
+                Instruction::SetI(Addr(target)) | Instruction::SetILong(Addr(target)) => {
+                    self.refer(target, Reference::Data);
+                    next_i = Some(target);
+                }
+                Instruction::Draw(_, _, Const(n)) => {
+                    if let Some(sprite) = i {
+                        let len = if n == 0 { 32 } else { n as u16 };
+                        self.sprites
+                            .extend((0..len).map(|offset| sprite.wrapping_add(offset)));
+                        self.refer(sprite, Reference::Sprite);
+                    }
+                }
+                Instruction::AddRegToI(_)
+                | Instruction::SetIToSpriteAddrVx(_)
+                | Instruction::SetIToBigSpriteAddrVx(_)
+                | Instruction::RegDump(_)
+                | Instruction::RegLoad(_) => next_i = None,
+                _ => {}
+            }
+            pending.push((next, next_i));
+        }
+    }
+
+    /// Split the program into lines of code and data.
+    /// Data is split wherever a label might go, and sprites are written a row per line.
+    fn items(&self) -> Vec<(u16, Item<'_>)> {
+        let mut items = Vec::new();
+        let mut address = PC_START as usize;
+        while address < self.end() {
+            let start = address;
+            let offset = start - PC_START as usize;
+            if let Some(instruction) = self.code.get(&(start as u16)) {
+                address += instruction.size() as usize;
+                items.push((start as u16, Item::Code(*instruction)));
+                continue;
+            }
+
+            address += 1;
+            if !self.is_sprite(start as u16) {
+                while address < self.end()
+                    && address - start < BYTES_PER_LINE
+                    && !self.code.contains_key(&(address as u16))
+                    && !self.references.contains_key(&(address as u16))
+                    && !self.is_sprite(address as u16)
+                {
+                    address += 1;
+                }
+            }
+            let bytes = &self.program[offset..address - PC_START as usize];
+            items.push((start as u16, Item::Data(bytes)));
+        }
+        items
+    }
+}
+
+/// Write an instruction, referring to addresses by their labels where possible.
+fn write_instruction(
+    f: &mut fmt::Formatter<'_>,
+    instruction: Instruction,
+    labels: &BTreeMap<u16, String>,
+) -> fmt::Result {
+    let label = match instruction {
+        Instruction::Goto(Addr(target))
+        | Instruction::Call(Addr(target))
+        | Instruction::SetI(Addr(target))
+        | Instruction::SetPcToV0PlusAddr(Addr(target))
+        | Instruction::SetILong(Addr(target)) => labels.get(&target),
+        _ => None,
+    };
+    match (instruction, label) {
+        (Instruction::Goto(_), Some(label)) => write!(f, "JP {}", label),
+        (Instruction::Call(_), Some(label)) => write!(f, "CALL {}", label),
+        (Instruction::SetI(_), Some(label)) => write!(f, "LD I, {}", label),
+        (Instruction::SetPcToV0PlusAddr(_), Some(label)) => write!(f, "JP V0, {}", label),
+        (Instruction::SetILong(_), Some(label)) => write!(f, "LD I, long {}", label),
+        _ => write!(f, "{}", instruction),
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items = self.items();
+
+        // Only addresses at the start of a line can be labelled,
+        // other references stay numeric so the program assembles to the same bytes.
+        let labels: BTreeMap<u16, String> = items
+            .iter()
+            .filter_map(|(address, _)| {
+                let reference = self.references.get(address)?;
+                Some((*address, format!("{}_{:03X}", reference.prefix(), address)))
+            })
+            .collect();
+
+        for (address, item) in items {
+            if let Some(label) = labels.get(&address) {
+                writeln!(f, "{}:", label)?;
+            }
+            write!(f, "    ")?;
+            match item {
+                Item::Code(instruction) => write_instruction(f, instruction, &labels)?,
+                Item::Data(bytes) => {
+                    let bytes: Vec<_> = bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+                    write!(f, "db {}", bytes.join(", "))?;
+                }
+            }
+            if let (Item::Data(&[row]), true) = (item, self.is_sprite(address)) {
+                let pixels: String = (0..8)
+                    .map(|bit| if row << bit & 0x80 != 0 { '#' } else { '.' })
+                    .collect();
+                write!(f, " ; {}", pixels)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn code_is_followed_and_labelled() {
+        let program = [
+            0xA2, 0x0A, // LD I, sprite_20A
+            0x22, 0x06, // CALL sub_206
+            0x12, 0x04, // JP loc_204
+            0xD0, 0x13, // DRW V0, V1, 3
+            0x00, 0xEE, // RET
+            0xF0, 0x90, 0xF0, // sprite
+        ];
+        let expected = "    LD I, sprite_20A
+    CALL sub_206
+loc_204:
+    JP loc_204
+sub_206:
+    DRW V0, V1, 3
+    RET
+sprite_20A:
+    db 0xF0 ; ####....
+    db 0x90 ; #..#....
+    db 0xF0 ; ####....
+";
+        assert_eq!(Disassembly::new(&program).to_string(), expected);
+    }
+
+    #[test]
+    fn unreachable_bytes_are_data() {
+        let program = [
+            0x30, 0x00, // SE V0, 0x00
+            0x00, 0xFD, // EXIT
+            0x00, 0xFD, // EXIT
+            0x00, 0xE0, 0x12, 0x34, 0xFF,
+        ];
+        let disassembly = Disassembly::new(&program);
+        assert_eq!(disassembly.instruction_at(0x204), Some(Instruction::Exit));
+        assert_eq!(disassembly.instruction_at(0x206), None);
+        assert!(disassembly
+            .to_string()
+            .ends_with("    db 0x00, 0xE0, 0x12, 0x34, 0xFF\n"));
+    }
+
+    #[test]
+    fn references_into_instructions_stay_numeric() {
+        let program = [
+            0x12, 0x03, // JP 0x203, into the middle of this jump
+        ];
+        assert_eq!(Disassembly::new(&program).to_string(), "    JP 0x203\n");
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{self, Write};
+
+/// Represents a sound device that plays the buzzer, which sounds while the sound timer is active.
+pub trait EmulatorAudio {
+    /// Called once per frame, with whether the buzzer sounds during that frame.
+    fn set_buzzer(&mut self, on: bool);
+
+    /// Called when an XO-CHIP program changes the audio pattern or its pitch.
+    /// The pattern is 128 bits played from the most significant bit of the first byte,
+    /// at 4000 * 2^((pitch - 64) / 48) bits per second.
+    fn set_pattern(&mut self, _pattern: &[u8; 16], _pitch: u8) {}
+
+    /// Called when a snapshot from before any pattern or pitch was loaded is restored,
+    /// so that the plain buzzer sounds again.
+    fn clear_pattern(&mut self) {}
+}
+
+/// An audio device that never makes a sound.
+pub struct DummyAudio;
+
+impl EmulatorAudio for DummyAudio {
+    fn set_buzzer(&mut self, _on: bool) {}
+}
+
+/// The frequency of the buzzer when no XO-CHIP audio pattern has been loaded.
+const BUZZER_FREQUENCY: f64 = 440.0;
+const FRAMES_PER_SECOND: u64 = 60;
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+/// An audio device that renders the buzzer to 16-bit mono PCM samples,
+/// which can be written to a WAV file.
+pub struct PcmAudio {
+    sample_rate: u32,
+    samples: Vec<i16>,
+    frames: u64,
+    pattern: Option<([u8; 16], u8)>,
+    phase: f64,
+}
+
+impl PcmAudio {
+    /// Create a new audio device rendering `sample_rate` samples per second.
+    pub fn new(sample_rate: u32) -> PcmAudio {
+        PcmAudio {
+            sample_rate,
+            samples: Vec::new(),
+            frames: 0,
+            pattern: None,
+            phase: 0.0,
+        }
+    }
+
+    /// The samples rendered so far.
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Write the samples rendered so far as a WAV file.
+    pub fn write_wav<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let data_len = 2 * self.samples.len() as u32;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_len).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        // Format chunk: PCM, one channel, 16 bits per sample
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&(2 * self.sample_rate).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_len.to_le_bytes())?;
+        for sample in &self.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// The current waveform position in cycles per second,
+    /// where each cycle is a full period of the square wave or a bit of the pattern.
+    fn rate(&self) -> f64 {
+        match self.pattern {
+            Some((_, pitch)) => 4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0),
+            None => 2.0 * BUZZER_FREQUENCY,
+        }
+    }
+
+    fn sample(&self) -> i16 {
+        let position = self.phase as usize;
+        let on = match &self.pattern {
+            Some((pattern, _)) => {
+                let bit = position % 128;
+                pattern[bit / 8] >> (7 - bit % 8) & 1 == 1
+            }
+            None => position & 1 == 0,
+        };
+        if on {
+            AMPLITUDE
+        } else {
+            -AMPLITUDE
+        }
+    }
+}
+
+impl EmulatorAudio for PcmAudio {
+    fn set_buzzer(&mut self, on: bool) {
+        // Render up to the end of this frame, avoiding drift when frames aren't a whole number of samples
+        self.frames += 1;
+        let end = (self.frames * self.sample_rate as u64 / FRAMES_PER_SECOND) as usize;
+        let step = self.rate() / self.sample_rate as f64;
+        while self.samples.len() < end {
+            if on {
+                self.samples.push(self.sample());
+                self.phase += step;
+            } else {
+                self.samples.push(0);
+            }
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        self.pattern = Some((*pattern, pitch));
+    }
+
+    fn clear_pattern(&mut self) {
+        self.pattern = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn renders_one_frame_of_samples_per_call() {
+        let mut audio = PcmAudio::new(6000);
+        audio.set_buzzer(false);
+        assert_eq!(audio.samples(), &[0; 100][..]);
+        audio.set_buzzer(true);
+        assert_eq!(audio.samples().len(), 200);
+        assert!(audio.samples()[100..].iter().all(|s| s.abs() == AMPLITUDE));
+    }
+
+    #[test]
+    fn plays_pattern_at_pitch() {
+        // At pitch 64, the pattern plays at 4000 bits per second,
+        // so every sample is a single bit at a sample rate of 4000.
+        let mut audio = PcmAudio::new(4000);
+        let mut pattern = [0; 16];
+        pattern[0] = 0b10100000;
+        audio.set_pattern(&pattern, 64);
+        audio.set_buzzer(true);
+        audio.set_buzzer(true);
+        assert_eq!(
+            audio.samples()[..4],
+            [AMPLITUDE, -AMPLITUDE, AMPLITUDE, -AMPLITUDE]
+        );
+        // The pattern repeats after 128 bits
+        assert_eq!(audio.samples()[128..132], audio.samples()[..4]);
+    }
+
+    #[test]
+    fn writes_wav_header() {
+        let mut audio = PcmAudio::new(6000);
+        audio.set_buzzer(true);
+        let mut wav = Vec::new();
+        audio.write_wav(&mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + 2 * 100);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(wav[24..28], 6000u32.to_le_bytes());
+        assert_eq!(wav[40..44], 200u32.to_le_bytes());
+    }
+}
@@ -1,13 +1,30 @@
 //! The CHIP-8 emulator as described at https://en.wikipedia.org/wiki/CHIP-8#Virtual_machine_description.
 
-use crate::emulator::input::{DummyInput, EmulatorInput};
+use crate::emulator::audio::{DummyAudio, EmulatorAudio};
+use crate::emulator::fault::Fault;
+use crate::emulator::input::{DummyInput, EmulatorInput, KeyEvent};
 use crate::emulator::instruction::*;
+use crate::emulator::memory_access::MemoryAccess;
 use crate::emulator::output::{DummyOutput, EmulatorOutput};
-
-const MEM_SIZE: usize = 4096;
-const NUM_REGISTERS: usize = 16;
-const STACK_SIZE: usize = 256;
-const PC_START: u16 = 0x200;
+use crate::emulator::quirks::Quirks;
+use crate::emulator::snapshot::{Snapshot, SnapshotError};
+use crate::emulator::trace::{TraceState, Tracer};
+use crate::util::crc32::crc32;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::ops::Range;
+
+pub(crate) const NUM_REGISTERS: usize = 16;
+/// The number of slots in the stack. The stack pointer is a single byte, as in snapshots and traces,
+/// so calls can only be nested 255 deep and the last slot is never used.
+pub(crate) const STACK_SIZE: usize = 256;
+/// The address programs are loaded at and start executing from.
+pub const PC_START: u16 = 0x200;
+pub(crate) const NUM_FLAGS: usize = 16;
+const SCREEN_WIDTH: usize = 64;
+const SCREEN_HEIGHT: usize = 32;
+pub(crate) const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
 const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -26,10 +43,37 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+/// The 8x10 SUPER-CHIP font, stored right after `FONT`.
+const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// How far `SetRegToGetKey` has come in waiting for a key to be pressed and released.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum KeyWait {
+    Press,
+    Release(u8),
+    Released(u8),
+}
 
-pub struct Emulator<I: EmulatorInput, O: EmulatorOutput> {
+pub struct Emulator<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio = DummyAudio> {
     // Standard fields
-    memory: [u8; MEM_SIZE],
+    memory: Vec<u8>,
     registers: [u8; NUM_REGISTERS],
     delay_timer: u8,
     sound_timer: u8,
@@ -38,8 +82,32 @@ pub struct Emulator<I: EmulatorInput, O: EmulatorOutput> {
     stack_pointer: u8,
     stack: [u16; STACK_SIZE],
 
+    // SUPER-CHIP fields
+    flags: [u8; NUM_FLAGS],
+    high_res: bool,
+    exited: bool,
+    waiting_for_vblank: bool,
+
+    // XO-CHIP fields
+    planes: u8,
+    drawn_planes: u8,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    /// Whether the audio pattern or pitch has been loaded, replacing the plain buzzer
+    audio_pattern_loaded: bool,
+
+    /// The checksum of the loaded program, to match snapshots against
+    rom_checksum: u32,
+    /// The memory accessed by the last instruction
+    memory_accesses: Vec<MemoryAccess>,
+    /// Set while `SetRegToGetKey` is waiting for a key
+    key_wait: Option<KeyWait>,
+    rng: StdRng,
+    tracer: Option<Tracer>,
+    quirks: Quirks,
     input: I,
     output: O,
+    audio: A,
 }
 
 impl Emulator<DummyInput, DummyOutput> {
@@ -52,10 +120,25 @@ impl Emulator<DummyInput, DummyOutput> {
 impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
     /// Create a new emulator with input and output
     pub fn new(input: I, output: O) -> Self {
-        let mut memory = [0; MEM_SIZE];
+        Emulator::with_quirks(input, output, Quirks::default())
+    }
+
+    /// Create a new emulator with input and output,
+    /// that resolves ambiguous instructions according to `quirks`.
+    pub fn with_quirks(input: I, output: O, quirks: Quirks) -> Self {
+        Emulator::with_audio(input, output, DummyAudio, quirks)
+    }
+}
+
+impl<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio> Emulator<I, O, A> {
+    /// Create a new emulator with input, output and audio,
+    /// that resolves ambiguous instructions according to `quirks`.
+    pub fn with_audio(input: I, output: O, audio: A, quirks: Quirks) -> Self {
+        let mut memory = vec![0; quirks.memory_size];
 
-        // Load font
+        // Load fonts
         memory[0..FONT.len()].copy_from_slice(&FONT);
+        memory[FONT.len()..FONT.len() + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
 
         Emulator {
             memory,
@@ -67,39 +150,293 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
             stack_pointer: 0,
             stack: [0; STACK_SIZE],
 
+            flags: [0; NUM_FLAGS],
+            high_res: false,
+            exited: false,
+            waiting_for_vblank: false,
+
+            planes: 1,
+            drawn_planes: 1,
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            audio_pattern_loaded: false,
+
+            rom_checksum: crc32(&[]),
+            memory_accesses: Vec::new(),
+            key_wait: None,
+            rng: StdRng::from_entropy(),
+            tracer: None,
+            quirks,
             input,
             output,
+            audio,
         }
     }
 
     /// Copy a program into memory at 0x200.
+    /// Anything that does not fit in memory is left out.
     pub fn load(&mut self, program: &[u8]) {
         let pc = self.program_counter as usize;
         let len = std::cmp::min(program.len(), self.memory.len() - pc);
-        self.memory[pc..pc + len].copy_from_slice(program);
+        self.memory[pc..pc + len].copy_from_slice(&program[..len]);
+        self.rom_checksum = crc32(program);
     }
 
-    /// Perform a single step, which will update timers,
-    /// then load an instruction and execute it.
-    pub fn step(&mut self) {
-        // Each opcode is two bytes
-        let left = self.memory[self.program_counter as usize];
-        let right = self.memory[self.program_counter as usize + 1];
-        let instruction = Instruction::from_two_u8(left, right);
+    /// Whether the program has exited with `00FD`.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
 
-        self.execute_single(instruction);
+    /// Whether the SUPER-CHIP 128x64 high resolution mode is active.
+    pub fn is_high_res(&self) -> bool {
+        self.high_res
     }
 
-    /// Execute many instructions in succession
-    pub fn execute_many(&mut self, instructions: &[Instruction]) {
-        for instruction in instructions {
-            self.execute_single(*instruction);
+    /// The XO-CHIP audio pattern buffer and the pitch to play it at.
+    pub fn audio_pattern(&self) -> (&[u8; AUDIO_PATTERN_SIZE], u8) {
+        (&self.audio_pattern, self.pitch)
+    }
+
+    /// The audio device the buzzer is played on.
+    pub fn audio(&self) -> &A {
+        &self.audio
+    }
+
+    /// The screen the program draws to.
+    pub fn output(&self) -> &O {
+        &self.output
+    }
+
+    /// The input device keys are read from.
+    pub fn input(&self) -> &I {
+        &self.input
+    }
+
+    /// Seed the random number generator used by `CXNN`,
+    /// which is otherwise seeded differently every time.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// The registers V0 to VF.
+    pub fn registers(&self) -> &[u8; NUM_REGISTERS] {
+        &self.registers
+    }
+
+    /// The address register I.
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// The address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// The return addresses of the subroutines being executed, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    /// The delay and sound timers.
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    /// All of memory, including the font and the loaded program.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Write a line for every executed instruction to `tracer`, or stop tracing with `None`.
+    /// Returns the previous tracer, which should be flushed.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// Change the registers V0 to VF, such as from a debugger.
+    pub fn registers_mut(&mut self) -> &mut [u8; NUM_REGISTERS] {
+        &mut self.registers
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    /// Continue execution from another address.
+    pub fn set_program_counter(&mut self, pc: u16) {
+        self.program_counter = pc;
+    }
+
+    /// Change the number of return addresses on the stack, keeping the ones below it.
+    pub fn set_stack_pointer(&mut self, sp: u8) {
+        self.stack_pointer = sp;
+    }
+
+    /// Change memory, such as from a debugger.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// The reads and writes of memory made by the last executed instruction, in order.
+    /// Fetching the instruction itself is not included.
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        &self.memory_accesses
+    }
+
+    /// The current screen width and height in pixels.
+    pub fn screen_size(&self) -> (usize, usize) {
+        if self.high_res {
+            (2 * SCREEN_WIDTH, 2 * SCREEN_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    /// Take a snapshot of the full state of the emulator, including the screen.
+    pub fn snapshot(&self) -> Snapshot {
+        let (width, height) = self.screen_size();
+        let mut framebuffer = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                framebuffer.push(self.output.get(x, y));
+            }
+        }
+
+        Snapshot {
+            rom_checksum: self.rom_checksum,
+            memory: self.memory.clone(),
+            registers: self.registers,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            i: self.i,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            flags: self.flags,
+            high_res: self.high_res,
+            exited: self.exited,
+            waiting_for_vblank: self.waiting_for_vblank,
+            planes: self.planes,
+            drawn_planes: self.drawn_planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            audio_pattern_loaded: self.audio_pattern_loaded,
+            framebuffer,
+        }
+    }
+
+    /// Restore the state of the emulator from a snapshot, and redraw the screen.
+    /// The snapshot must have been taken with the same program loaded,
+    /// and the same amount of memory.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.rom_checksum != self.rom_checksum {
+            return Err(SnapshotError::RomMismatch {
+                expected: self.rom_checksum,
+                found: snapshot.rom_checksum,
+            });
+        }
+        if snapshot.memory.len() != self.memory.len() {
+            return Err(SnapshotError::MemorySizeMismatch {
+                expected: self.memory.len(),
+                found: snapshot.memory.len(),
+            });
         }
+
+        self.memory.copy_from_slice(&snapshot.memory);
+        self.registers = snapshot.registers;
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.i = snapshot.i;
+        self.program_counter = snapshot.program_counter;
+        self.stack_pointer = snapshot.stack_pointer;
+        self.stack = snapshot.stack;
+        self.flags = snapshot.flags;
+        self.exited = snapshot.exited;
+        self.waiting_for_vblank = snapshot.waiting_for_vblank;
+        // Any key wait starts over, since the key events it has seen belong to another state
+        self.key_wait = None;
+        self.planes = snapshot.planes;
+        self.drawn_planes = snapshot.drawn_planes;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.audio_pattern_loaded = snapshot.audio_pattern_loaded;
+        if self.audio_pattern_loaded {
+            self.audio.set_pattern(&self.audio_pattern, self.pitch);
+        } else {
+            self.audio.clear_pattern();
+        }
+
+        // Resizing clears the screen, so only the set pixels need to be drawn
+        self.high_res = snapshot.high_res;
+        let (width, height) = self.screen_size();
+        self.output.resize(width, height);
+        for (index, state) in snapshot.framebuffer.iter().enumerate() {
+            if *state != 0 {
+                self.output.set(index % width, index / width, *state);
+            }
+        }
+        self.output.refresh();
+
+        Ok(())
+    }
+
+    /// Run a single frame of 1/60th of a second,
+    /// executing up to `cycles_per_frame` instructions before updating the timers once.
+    /// The frame ends early if the program exits,
+    /// or draws a sprite with the `display_wait` quirk enabled.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), Fault> {
+        for _ in 0..cycles_per_frame {
+            if self.exited || self.waiting_for_vblank {
+                break;
+            }
+            self.step()?;
+        }
+        self.end_frame();
+        Ok(())
+    }
+
+    /// Whether the program is waiting for the next frame after drawing a sprite
+    /// with the `display_wait` quirk enabled, which ends frames early.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
     }
 
-    /// Execute a single instruction
-    pub fn execute_single(&mut self, instruction: Instruction) {
-        // Update timers
+    /// Whether the program is waiting for a key to be pressed and released with `FX0A`.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    /// Finish the current frame like `run_frame` does,
+    /// for frontends that execute instructions one `step` at a time.
+    pub fn end_frame(&mut self) {
+        self.waiting_for_vblank = false;
+        self.input.end_frame();
+        self.poll_keys();
+        self.tick_timers();
+    }
+
+    /// Take the key events from the input, which only matter while waiting for a key.
+    /// Events are taken every frame, so a key wait never sees stale ones.
+    fn poll_keys(&mut self) {
+        while let Some(event) = self.input.poll_event() {
+            self.key_wait = match (self.key_wait, event) {
+                (Some(KeyWait::Press), KeyEvent::Pressed(key)) => Some(KeyWait::Release(key)),
+                (Some(KeyWait::Release(key)), KeyEvent::Released(released)) if key == released => {
+                    Some(KeyWait::Released(key))
+                }
+                (key_wait, _) => key_wait,
+            };
+        }
+    }
+
+    /// Count the delay and sound timers down by one,
+    /// sounding the buzzer for this frame if the sound timer is active.
+    /// Should be called 60 times per second,
+    /// unless the emulator is driven by `run_frame`, which does it automatically.
+    pub fn tick_timers(&mut self) {
+        self.audio.set_buzzer(self.sound_timer > 0);
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -107,23 +444,143 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+    }
+
+    /// Perform a single step, which will load an instruction and execute it.
+    /// Does nothing once the program has exited.
+    pub fn step(&mut self) -> Result<(), Fault> {
+        if self.exited {
+            return Ok(());
+        }
+
+        let pc = self.program_counter;
+
+        // Each opcode is two bytes, except for the four byte `SetILong`
+        let start = self
+            .memory_range(pc, 2)
+            .ok_or(Fault::PcOutOfBounds { pc })?
+            .start;
+        let end = std::cmp::min(start + 4, self.memory.len());
+        let instruction = Instruction::decode(&self.memory[start..end]).map_err(|e| e.at(pc))?;
+
+        self.execute_single(instruction)
+    }
+
+    /// Execute many instructions in succession, stopping at the first fault.
+    pub fn execute_many(&mut self, instructions: &[Instruction]) -> Result<(), Fault> {
+        for instruction in instructions {
+            self.execute_single(*instruction)?;
+        }
+        Ok(())
+    }
+
+    /// Execute a single instruction.
+    /// If the instruction faults, the program counter is left pointing at it.
+    pub fn execute_single(&mut self, instruction: Instruction) -> Result<(), Fault> {
+        self.memory_accesses.clear();
+        let pc = self.program_counter;
+        let result = self.execute(pc, instruction);
+        if result.is_err() {
+            self.program_counter = pc;
+        } else if let Some(tracer) = &mut self.tracer {
+            let state = TraceState {
+                registers: &self.registers,
+                i: self.i,
+                stack_pointer: self.stack_pointer,
+                delay_timer: self.delay_timer,
+                sound_timer: self.sound_timer,
+            };
+            if let Err(e) = tracer.trace(pc, instruction, state) {
+                log::error!("Stopped tracing: {}", e);
+                self.tracer = None;
+            }
+        }
+        result
+    }
+
+    /// Get the memory range `start..start + len` if it fits in memory.
+    fn memory_range(&self, start: u16, len: usize) -> Option<Range<usize>> {
+        let start = start as usize;
+        let end = start + len;
+        if end <= self.memory.len() {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+
+    fn execute(&mut self, pc: u16, instruction: Instruction) -> Result<(), Fault> {
+        let out_of_bounds = |address| Fault::MemoryOutOfBounds {
+            pc,
+            instruction,
+            address,
+        };
 
         log::trace!("{:?}", instruction);
 
-        self.program_counter += 2;
+        self.program_counter = self.program_counter.wrapping_add(instruction.size());
 
         match instruction {
-            // Clear the screen
+            // Scroll the screen down N pixels
+            Instruction::ScrollDown(Const(n)) => {
+                self.scroll(0, n as isize);
+            }
+
+            // Scroll the screen up N pixels
+            Instruction::ScrollUp(Const(n)) => {
+                self.scroll(0, -(n as isize));
+            }
+
+            // Clear the selected planes
             Instruction::ClearScreen => {
-                self.output.clear();
+                if self.planes & self.drawn_planes == self.drawn_planes {
+                    self.output.clear();
+                    self.drawn_planes = 0;
+                } else {
+                    let (width, height) = self.screen_size();
+                    for y in 0..height {
+                        for x in 0..width {
+                            let state = self.output.get(x, y);
+                            self.output.set(x, y, state & !self.planes);
+                        }
+                    }
+                }
             }
 
             // Return to the previous call site via the stack.
             Instruction::Return => {
+                if self.stack_pointer == 0 {
+                    return Err(Fault::StackUnderflow { pc, instruction });
+                }
                 self.stack_pointer -= 1;
                 self.program_counter = self.stack[self.stack_pointer as usize]; // Jump back via stack
             }
 
+            // Scroll the screen 4 pixels right
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+            }
+
+            // Scroll the screen 4 pixels left
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+            }
+
+            // Stop executing the program
+            Instruction::Exit => {
+                self.exited = true;
+            }
+
+            // Switch to 64x32 pixels
+            Instruction::LowRes => {
+                self.set_high_res(false);
+            }
+
+            // Switch to 128x64 pixels
+            Instruction::HighRes => {
+                self.set_high_res(true);
+            }
+
             // Go to a specific memory address
             Instruction::Goto(Addr(addr)) => {
                 self.program_counter = addr;
@@ -131,6 +588,10 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
 
             // Store the current address on the stack, then jump to the specified address
             Instruction::Call(Addr(addr)) => {
+                // The stack pointer can't count past 255, see `STACK_SIZE`
+                if self.stack_pointer == u8::MAX {
+                    return Err(Fault::StackOverflow { pc, instruction });
+                }
                 self.stack[self.stack_pointer as usize] = self.program_counter; // Store current address
                 self.stack_pointer += 1;
                 self.program_counter = addr; // Jump to addr
@@ -139,19 +600,43 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
             // If the register equals the constant, skip the next instruction
             Instruction::IfRegEqConst(Reg(x), Const(n)) => {
                 if self.registers[x as usize] == n {
-                    self.program_counter += 2;
+                    self.skip_next();
                 }
             }
 
             Instruction::IfRegNeqConst(Reg(x), Const(n)) => {
                 if self.registers[x as usize] != n {
-                    self.program_counter += 2;
+                    self.skip_next();
                 }
             }
 
             Instruction::IfRegEqReg(Reg(x), Reg(y)) => {
                 if self.registers[x as usize] == self.registers[y as usize] {
-                    self.program_counter += 2;
+                    self.skip_next();
+                }
+            }
+
+            // Save the registers from Vx to Vy, in that order, at I
+            Instruction::SaveRegRange(Reg(x), Reg(y)) => {
+                let regs = Self::reg_range(x, y);
+                let i = self
+                    .memory_range(self.i, regs.len())
+                    .ok_or_else(|| out_of_bounds(self.i))?
+                    .start;
+                for (offset, reg_no) in regs.into_iter().enumerate() {
+                    self.write_memory(i + offset, self.registers[reg_no]);
+                }
+            }
+
+            // Load the registers from Vx to Vy, in that order, from I
+            Instruction::LoadRegRange(Reg(x), Reg(y)) => {
+                let regs = Self::reg_range(x, y);
+                let i = self
+                    .memory_range(self.i, regs.len())
+                    .ok_or_else(|| out_of_bounds(self.i))?
+                    .start;
+                for (offset, reg_no) in regs.into_iter().enumerate() {
+                    self.registers[reg_no] = self.read_memory(i + offset);
                 }
             }
 
@@ -170,14 +655,17 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
 
             Instruction::BitwiseOr(Reg(x), Reg(y)) => {
                 self.registers[x as usize] |= self.registers[y as usize];
+                self.reset_vf_after_logic();
             }
 
             Instruction::BitwiseAnd(Reg(x), Reg(y)) => {
                 self.registers[x as usize] &= self.registers[y as usize];
+                self.reset_vf_after_logic();
             }
 
             Instruction::BitwiseXor(Reg(x), Reg(y)) => {
                 self.registers[x as usize] ^= self.registers[y as usize];
+                self.reset_vf_after_logic();
             }
 
             // Increment the value of a register by the value of another
@@ -200,11 +688,11 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
                 self.registers[0xF] = if overflow { 0 } else { 1 };
             }
 
-            // Store least significant bit in VF then shift right
-            Instruction::BitshiftRight(Reg(x)) => {
-                let value = self.registers[x as usize];
+            // Shift right, then store the shifted out bit in VF
+            Instruction::BitshiftRight(Reg(x), Reg(y)) => {
+                let value = self.shift_operand(x, y);
+                self.registers[x as usize] = value >> 1;
                 self.registers[0xF] = value & 1;
-                self.registers[x as usize] >>= 1;
             }
 
             // Set VF to 0 when there's a borrow, and 1 when there isn't.
@@ -216,16 +704,16 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
                 self.registers[0xF] = if borrow { 0 } else { 1 };
             }
 
-            // Store most significant bit in VF then shift left
-            Instruction::BitshiftLeft(Reg(x)) => {
-                let value = self.registers[x as usize];
+            // Shift left, then store the shifted out bit in VF
+            Instruction::BitshiftLeft(Reg(x), Reg(y)) => {
+                let value = self.shift_operand(x, y);
+                self.registers[x as usize] = value << 1;
                 self.registers[0xF] = (value & 0b10000000) >> 7;
-                self.registers[x as usize] <<= 1;
             }
 
             Instruction::IfRegNeqReg(Reg(x), Reg(y)) => {
                 if self.registers[x as usize] != self.registers[y as usize] {
-                    self.program_counter += 2;
+                    self.skip_next();
                 }
             }
 
@@ -233,66 +721,132 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
                 self.i = addr;
             }
 
+            // Jump to NNN plus V0, or XNN plus Vx with the jump quirk
             Instruction::SetPcToV0PlusAddr(Addr(addr)) => {
-                self.program_counter = self.registers[0] as u16 + addr;
+                let x = if self.quirks.jump_uses_vx {
+                    (addr >> 8) as usize & 0xF
+                } else {
+                    0
+                };
+                self.program_counter = self.registers[x] as u16 + addr;
             }
 
             Instruction::SetVxRand(Reg(x), Const(n)) => {
-                self.registers[x as usize] = rand::random::<u8>() & n;
+                self.registers[x as usize] = self.rng.gen::<u8>() & n;
             }
 
-            // TOOD: Implement fully, with xor of pixels.
+            // Draw a sprite at (Vx, Vy), wrapping the starting coordinates around the screen.
+            // The sprite is 8xN pixels, or 16x16 pixels when N is 0.
+            // With several planes selected, the sprite for each plane follows the previous one.
             Instruction::Draw(Reg(x), Reg(y), Const(sprite_height)) => {
                 // Get coordinates
-                let x_coord = self.registers[x as usize] as usize;
-                let y_coord = self.registers[y as usize] as usize;
-
-                // Get sprite, each row is 8 bits
-                let sprite_addr = self.i as usize;
-                let sprite_data: &[u8] =
-                    &self.memory[sprite_addr..sprite_addr + sprite_height as usize];
+                let (screen_width, screen_height) = self.screen_size();
+                let x_coord = self.registers[x as usize] as usize % screen_width;
+                let y_coord = self.registers[y as usize] as usize % screen_height;
+
+                // Get sprite, each row is 8 or 16 bits
+                let (sprite_width, sprite_height) = match sprite_height {
+                    0 => (16, 16),
+                    n => (8, n as usize),
+                };
+                let bytes_per_row = sprite_width / 8;
+                let planes: Vec<u8> = (0..2)
+                    .map(|n| 1 << n)
+                    .filter(|plane| self.planes & plane != 0)
+                    .collect();
+                let sprite_range = self
+                    .memory_range(self.i, planes.len() * bytes_per_row * sprite_height)
+                    .ok_or_else(|| out_of_bounds(self.i))?;
+                self.record_reads(sprite_range.clone());
+                let sprite_data: &[u8] = &self.memory[sprite_range];
+                self.drawn_planes |= self.planes;
 
                 // Write to screen
                 let mut any_collisions = 0;
-                for (h, row) in sprite_data.iter().enumerate() {
-                    for w in 0..8 {
-                        let new_pixel = row >> (7 - w) & 1; // Get bit number `bit_idx`
-                        let old_pixel = self.output.get(x_coord + w, y_coord + h);
-                        let xored_pixel = old_pixel ^ new_pixel; // XOR old pixel with new pixel
-                        self.output.set(x_coord + w, y_coord + h, xored_pixel); // Save xor'ed pixel
-
-                        // Set pixel was unset, so we set the collision flag
-                        if old_pixel == 1 && xored_pixel == 0 {
-                            any_collisions = 1;
+                let plane_sprites = sprite_data.chunks(bytes_per_row * sprite_height);
+                for (plane, plane_sprite) in planes.into_iter().zip(plane_sprites) {
+                    for (h, row) in plane_sprite.chunks(bytes_per_row).enumerate() {
+                        let row = row.iter().fold(0u16, |acc, byte| acc << 8 | *byte as u16);
+                        for w in 0..sprite_width {
+                            // Pixels past the edge are either clipped or wrapped
+                            let (mut px, mut py) = (x_coord + w, y_coord + h);
+                            if px >= screen_width || py >= screen_height {
+                                if self.quirks.clip_sprites {
+                                    continue;
+                                }
+                                px %= screen_width;
+                                py %= screen_height;
+                            }
+
+                            // Skip unset sprite pixels, since XOR with 0 changes nothing
+                            if row >> (sprite_width - 1 - w) & 1 == 0 {
+                                continue;
+                            }
+                            let old_pixel = self.output.get(px, py);
+                            self.output.set(px, py, old_pixel ^ plane); // Save xor'ed pixel
+
+                            // Set pixel was unset, so we set the collision flag
+                            if old_pixel & plane != 0 {
+                                any_collisions = 1;
+                            }
                         }
                     }
                 }
 
                 // Set VF collision flag
                 self.registers[0xF] = any_collisions;
+                self.waiting_for_vblank = self.quirks.display_wait;
             }
 
             // Skip if the key in Vx is pressed
             Instruction::IfKeyEqVx(Reg(x)) => {
-                if self.input.get_key() == Some(self.registers[x as usize]) {
-                    self.program_counter += 2;
+                if self.input.is_pressed(self.registers[x as usize]) {
+                    self.skip_next();
                 }
             }
 
             // Skip if the key in Vx isn't pressed
             Instruction::IfKeyNeqVx(Reg(x)) => {
-                if self.input.get_key() != Some(self.registers[x as usize]) {
-                    self.program_counter += 2;
+                if !self.input.is_pressed(self.registers[x as usize]) {
+                    self.skip_next();
                 }
             }
 
+            Instruction::SetILong(Addr(addr)) => {
+                self.i = addr;
+            }
+
+            // Select the planes to draw to, clear and scroll, as a bitmask
+            Instruction::SelectPlanes(Const(n)) => {
+                self.planes = n & 0b11;
+            }
+
+            // Load 16 bytes at I into the audio pattern buffer
+            Instruction::LoadAudioPattern => {
+                let range = self
+                    .memory_range(self.i, AUDIO_PATTERN_SIZE)
+                    .ok_or_else(|| out_of_bounds(self.i))?;
+                self.record_reads(range.clone());
+                self.audio_pattern.copy_from_slice(&self.memory[range]);
+                self.audio_pattern_loaded = true;
+                self.audio.set_pattern(&self.audio_pattern, self.pitch);
+            }
+
             Instruction::SetRegToDelayTimer(Reg(x)) => {
                 self.registers[x as usize] = self.delay_timer;
             }
 
-            // Get a key press (blocking)
+            // Wait for a key to be pressed and released, like the COSMAC VIP,
+            // by executing this instruction again until it is
             Instruction::SetRegToGetKey(Reg(x)) => {
-                self.registers[x as usize] = self.input.get_key_blocking();
+                self.key_wait.get_or_insert(KeyWait::Press);
+                self.poll_keys();
+                if let Some(KeyWait::Released(key)) = self.key_wait {
+                    self.registers[x as usize] = key;
+                    self.key_wait = None;
+                } else {
+                    self.program_counter = pc;
+                }
             }
 
             Instruction::SetDelayTimerToReg(Reg(x)) => {
@@ -304,46 +858,185 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
             }
 
             Instruction::AddRegToI(Reg(x)) => {
-                self.i += self.registers[x as usize] as u16;
+                self.i = self
+                    .i
+                    .checked_add(self.registers[x as usize] as u16)
+                    .ok_or(Fault::IOverflow { pc, instruction })?;
             }
 
-            // Set i to character address. Each font element is 5 bytes wide.
+            // Set i to character address. Each font element is 5 bytes wide,
+            // and only the low nibble selects the digit.
             Instruction::SetIToSpriteAddrVx(Reg(x)) => {
-                self.i = 5 * self.registers[x as usize] as u16;
+                let digit = self.registers[x as usize] as u16 & 0xF;
+                self.i = 5 * digit;
+            }
+
+            // Set i to big character address. Each big font element is 10 bytes wide.
+            Instruction::SetIToBigSpriteAddrVx(Reg(x)) => {
+                let digit = self.registers[x as usize] as u16 & 0xF;
+                self.i = FONT.len() as u16 + 10 * digit;
             }
 
             Instruction::SetIToBcdOfReg(Reg(x)) => {
-                let i = self.i as usize;
+                let i = self
+                    .memory_range(self.i, 3)
+                    .ok_or_else(|| out_of_bounds(self.i))?
+                    .start;
 
                 // Get ones place
                 let ones = self.registers[x as usize];
-                self.memory[i + 2] = (ones % 10) as u8;
+                self.write_memory(i + 2, ones % 10);
 
                 // Get tens place
                 let tens = ones / 10;
-                self.memory[i + 1] = (tens % 10) as u8;
+                self.write_memory(i + 1, tens % 10);
 
                 // Get hundredths place
                 let hundredths = tens / 10;
-                self.memory[i] = (hundredths % 10) as u8;
+                self.write_memory(i, hundredths % 10);
+            }
+
+            Instruction::SetPitchToReg(Reg(x)) => {
+                self.pitch = self.registers[x as usize];
+                self.audio_pattern_loaded = true;
+                self.audio.set_pattern(&self.audio_pattern, self.pitch);
             }
 
             // Dump register values up to Vx
             Instruction::RegDump(Reg(x)) => {
-                let i = self.i as usize;
+                let i = self
+                    .memory_range(self.i, x as usize + 1)
+                    .ok_or_else(|| out_of_bounds(self.i))?
+                    .start;
                 for reg_no in 0..=x as usize {
-                    self.memory[i + reg_no] = self.registers[reg_no];
+                    self.write_memory(i + reg_no, self.registers[reg_no]);
                 }
+                // I wraps around like in Octo when the registers end at the top of 64 KiB of memory
+                self.i = self
+                    .i
+                    .wrapping_add(self.quirks.load_store_increment.amount(x));
             }
 
             // Load register values up to Vx
             Instruction::RegLoad(Reg(x)) => {
-                let i = self.i as usize;
+                let i = self
+                    .memory_range(self.i, x as usize + 1)
+                    .ok_or_else(|| out_of_bounds(self.i))?
+                    .start;
                 for reg_no in 0..=x as usize {
-                    self.registers[reg_no] = self.memory[i + reg_no];
+                    self.registers[reg_no] = self.read_memory(i + reg_no);
                 }
+                self.i = self
+                    .i
+                    .wrapping_add(self.quirks.load_store_increment.amount(x));
+            }
+
+            // Save register values up to Vx in the flags
+            Instruction::SaveFlags(Reg(x)) => {
+                let n = x as usize + 1;
+                self.flags[..n].copy_from_slice(&self.registers[..n]);
+            }
+
+            // Load register values up to Vx from the flags
+            Instruction::LoadFlags(Reg(x)) => {
+                let n = x as usize + 1;
+                self.registers[..n].copy_from_slice(&self.flags[..n]);
             }
         };
+
+        Ok(())
+    }
+
+    fn read_memory(&mut self, address: usize) -> u8 {
+        self.record_reads(address..address + 1);
+        self.memory[address]
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+        self.memory_accesses.push(MemoryAccess::Write {
+            address: address as u16,
+            value,
+        });
+    }
+
+    fn record_reads(&mut self, range: Range<usize>) {
+        for address in range {
+            self.memory_accesses.push(MemoryAccess::Read {
+                address: address as u16,
+                value: self.memory[address],
+            });
+        }
+    }
+
+    /// Skip the next instruction, which may be four bytes long.
+    fn skip_next(&mut self) {
+        let pc = self.program_counter as usize;
+        let next_is_long = self.memory.get(pc..pc + 2) == Some(&[0xF0, 0x00]);
+        let size = if next_is_long { 4 } else { 2 };
+        self.program_counter = self.program_counter.wrapping_add(size);
+    }
+
+    /// The register numbers from x to y, in descending order if x > y.
+    fn reg_range(x: u8, y: u8) -> Vec<usize> {
+        if x <= y {
+            (x as usize..=y as usize).collect()
+        } else {
+            (y as usize..=x as usize).rev().collect()
+        }
+    }
+
+    fn set_high_res(&mut self, high_res: bool) {
+        self.high_res = high_res;
+        let (width, height) = self.screen_size();
+        self.output.resize(width, height);
+    }
+
+    /// Move every pixel in the selected planes by (dx, dy), filling in with unset pixels.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.screen_size();
+        let (width, height) = (width as isize, height as isize);
+
+        // Visit pixels in the opposite direction of the scroll,
+        // so that no pixel is overwritten before it has been moved.
+        let xs: Vec<isize> = if dx > 0 {
+            (0..width).rev().collect()
+        } else {
+            (0..width).collect()
+        };
+        let ys: Vec<isize> = if dy > 0 {
+            (0..height).rev().collect()
+        } else {
+            (0..height).collect()
+        };
+
+        for &y in &ys {
+            for &x in &xs {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    self.output.get(from_x as usize, from_y as usize) & self.planes
+                } else {
+                    0
+                };
+                let kept = self.output.get(x as usize, y as usize) & !self.planes;
+                self.output.set(x as usize, y as usize, kept | moved);
+            }
+        }
+    }
+
+    /// The value shifted by `8XY6` and `8XYE`.
+    fn shift_operand(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y as usize]
+        } else {
+            self.registers[x as usize]
+        }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
     }
 }
 
@@ -351,6 +1044,8 @@ impl<I: EmulatorInput, O: EmulatorOutput> Emulator<I, O> {
 mod tests {
 
     use super::*;
+    use crate::emulator::audio::PcmAudio;
+    use crate::emulator::input::KeyEvent;
     use test_case::test_case;
 
     const X: u8 = 0xA;
@@ -362,19 +1057,21 @@ mod tests {
         emulator.output.set(0, 0, 1);
         emulator.output.set(4, 8, 2);
         emulator.output.set(3, 5, 3);
-        emulator.execute_single(Instruction::ClearScreen);
+        emulator.execute_single(Instruction::ClearScreen).unwrap();
         assert_eq!(emulator.output.get(0, 0), 0);
         assert_eq!(emulator.output.get(4, 8), 0);
         assert_eq!(emulator.output.get(3, 5), 0);
     }
 
-    #[test_case(0x200; "when addr is 0x200")]
-    #[test_case(0x250; "when addr is 0x250")]
-    #[test_case(0x350; "when addr is 0x350")]
-    fn goto_goes_to(addr: u16) {
+    #[test_case(0x200 => 0x200; "when addr is 0x200")]
+    #[test_case(0x250 => 0x250; "when addr is 0x250")]
+    #[test_case(0x350 => 0x350; "when addr is 0x350")]
+    fn goto_goes_to(addr: u16) -> u16 {
         let mut emulator = Emulator::dummy();
-        emulator.execute_single(Instruction::Goto(Addr(addr)));
-        assert_eq!(emulator.program_counter, addr);
+        emulator
+            .execute_single(Instruction::Goto(Addr(addr)))
+            .unwrap();
+        emulator.program_counter
     }
 
     #[test]
@@ -393,18 +1090,112 @@ mod tests {
         emulator.load(&program);
 
         // Run the program
-        emulator.step(); // Call 0x206
+        emulator.step().unwrap(); // Call 0x206
         assert_eq!(emulator.program_counter, 0x206);
-        emulator.step(); // Return to 202
+        emulator.step().unwrap(); // Return to 202
+        assert_eq!(emulator.program_counter, 0x202);
+    }
+
+    #[test]
+    fn step_reports_unknown_opcode() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x60, 0x01, 0xFF, 0xFF]);
+        emulator.step().unwrap();
+        assert_eq!(
+            emulator.step(),
+            Err(Fault::InvalidOpcode(DecodeError {
+                opcode: 0xFFFF,
+                address: Some(0x202)
+            }))
+        );
+        // The faulting instruction is not skipped
         assert_eq!(emulator.program_counter, 0x202);
     }
 
+    #[test]
+    fn return_with_empty_stack_faults() {
+        let mut emulator = Emulator::dummy();
+        assert_eq!(
+            emulator.execute_single(Instruction::Return),
+            Err(Fault::StackUnderflow {
+                pc: 0x200,
+                instruction: Instruction::Return
+            })
+        );
+        assert_eq!(emulator.program_counter, 0x200);
+    }
+
+    #[test]
+    fn call_with_full_stack_faults() {
+        let mut emulator = Emulator::dummy();
+        let call = Instruction::Call(Addr(0x200));
+        for _ in 0..STACK_SIZE - 1 {
+            emulator.execute_single(call).unwrap();
+        }
+        assert_eq!(emulator.stack().len(), 255);
+        assert_eq!(
+            emulator.execute_single(call),
+            Err(Fault::StackOverflow {
+                pc: 0x200,
+                instruction: call
+            })
+        );
+    }
+
+    #[test]
+    fn step_past_end_of_memory_faults() {
+        let mut emulator = Emulator::dummy();
+        let pc = emulator.memory.len() as u16 - 1;
+        emulator
+            .execute_single(Instruction::Goto(Addr(pc)))
+            .unwrap();
+        assert_eq!(emulator.step(), Err(Fault::PcOutOfBounds { pc }));
+    }
+
+    #[test_case(Instruction::Draw(Reg(X), Reg(Y), Const(2)) => 0x202; "draw")]
+    #[test_case(Instruction::SetIToBcdOfReg(Reg(X)) => 0x202; "bcd")]
+    #[test_case(Instruction::RegDump(Reg(X)) => 0x202; "reg dump")]
+    #[test_case(Instruction::RegLoad(Reg(X)) => 0x202; "reg load")]
+    fn memory_access_out_of_bounds_faults(instruction: Instruction) -> u16 {
+        let mut emulator = Emulator::dummy();
+        let address = emulator.memory.len() as u16 - 1;
+        emulator
+            .execute_single(Instruction::SetI(Addr(address)))
+            .unwrap();
+        assert_eq!(
+            emulator.execute_single(instruction),
+            Err(Fault::MemoryOutOfBounds {
+                pc: 0x202,
+                instruction,
+                address
+            })
+        );
+        emulator.program_counter
+    }
+
+    #[test]
+    fn add_reg_to_i_overflow_faults() {
+        let mut emulator = Emulator::dummy();
+        emulator.i = 0xFFFF;
+        emulator.registers[X as usize] = 1;
+        let instruction = Instruction::AddRegToI(Reg(X));
+        assert_eq!(
+            emulator.execute_single(instruction),
+            Err(Fault::IOverflow {
+                pc: 0x200,
+                instruction
+            })
+        );
+    }
+
     #[test_case(0, 0 => 0x204; "skips when equal")]
     #[test_case(3, 8 => 0x202; "does not skip when not equal")]
     fn if_reg_eq_const(reg_value: u8, const_value: u8) -> u16 {
         let mut emulator = Emulator::dummy();
         emulator.registers[X as usize] = reg_value;
-        emulator.execute_single(Instruction::IfRegEqConst(Reg(X), Const(const_value)));
+        emulator
+            .execute_single(Instruction::IfRegEqConst(Reg(X), Const(const_value)))
+            .unwrap();
         emulator.program_counter
     }
 
@@ -413,7 +1204,9 @@ mod tests {
     fn if_reg_neq_const(reg_value: u8, const_value: u8) -> u16 {
         let mut emulator = Emulator::dummy();
         emulator.registers[X as usize] = reg_value;
-        emulator.execute_single(Instruction::IfRegNeqConst(Reg(X), Const(const_value)));
+        emulator
+            .execute_single(Instruction::IfRegNeqConst(Reg(X), Const(const_value)))
+            .unwrap();
         emulator.program_counter
     }
 
@@ -421,20 +1214,28 @@ mod tests {
     fn if_reg_eq_reg() {
         let mut emulator = Emulator::dummy();
 
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(3)),
-            Instruction::SetRegToConst(Reg(Y), Const(5)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(3)),
+                Instruction::SetRegToConst(Reg(Y), Const(5)),
+            ])
+            .unwrap();
 
         // Should not skip instruction
         assert_eq!(emulator.program_counter, 0x204);
-        emulator.execute_single(Instruction::IfRegEqReg(Reg(X), Reg(Y)));
+        emulator
+            .execute_single(Instruction::IfRegEqReg(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x206);
 
         // Should skip instruction
-        emulator.execute_single(Instruction::SetRegToConst(Reg(Y), Const(3)));
+        emulator
+            .execute_single(Instruction::SetRegToConst(Reg(Y), Const(3)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x208);
-        emulator.execute_single(Instruction::IfRegEqReg(Reg(X), Reg(Y)));
+        emulator
+            .execute_single(Instruction::IfRegEqReg(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x20C);
     }
 
@@ -443,7 +1244,9 @@ mod tests {
         let mut emulator = Emulator::dummy();
         let value = 7;
         assert_eq!(emulator.registers[X as usize], 0);
-        emulator.execute_single(Instruction::SetRegToConst(Reg(X), Const(value)));
+        emulator
+            .execute_single(Instruction::SetRegToConst(Reg(X), Const(value)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value);
     }
 
@@ -452,53 +1255,65 @@ mod tests {
         let mut emulator = Emulator::dummy();
         let value = 7;
         assert_eq!(emulator.registers[X as usize], 0);
-        emulator.execute_single(Instruction::IncRegByConst(Reg(X), Const(value)));
+        emulator
+            .execute_single(Instruction::IncRegByConst(Reg(X), Const(value)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value);
-        emulator.execute_single(Instruction::IncRegByConst(Reg(X), Const(value)));
+        emulator
+            .execute_single(Instruction::IncRegByConst(Reg(X), Const(value)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], 2 * value);
     }
 
     #[test]
     fn set_reg_to_reg() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(4)),
-            Instruction::SetRegToConst(Reg(Y), Const(8)),
-            Instruction::SetRegToReg(Reg(X), Reg(Y)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(4)),
+                Instruction::SetRegToConst(Reg(Y), Const(8)),
+                Instruction::SetRegToReg(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], 8);
     }
 
     #[test]
     fn bitwise_or() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(0xA), Const(0b0101)),
-            Instruction::SetRegToConst(Reg(0xB), Const(0b1100)),
-            Instruction::BitwiseOr(Reg(0xA), Reg(0xB)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(0xA), Const(0b0101)),
+                Instruction::SetRegToConst(Reg(0xB), Const(0b1100)),
+                Instruction::BitwiseOr(Reg(0xA), Reg(0xB)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[0xA], 0b1101);
     }
 
     #[test]
     fn bitwise_and() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(0xA), Const(0b0101)),
-            Instruction::SetRegToConst(Reg(0xB), Const(0b1101)),
-            Instruction::BitwiseAnd(Reg(0xA), Reg(0xB)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(0xA), Const(0b0101)),
+                Instruction::SetRegToConst(Reg(0xB), Const(0b1101)),
+                Instruction::BitwiseAnd(Reg(0xA), Reg(0xB)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[0xA], 0b0101);
     }
 
     #[test]
     fn bitwise_xor() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(0xA), Const(0b010101)),
-            Instruction::SetRegToConst(Reg(0xB), Const(0b110111)),
-            Instruction::BitwiseXor(Reg(0xA), Reg(0xB)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(0xA), Const(0b010101)),
+                Instruction::SetRegToConst(Reg(0xB), Const(0b110111)),
+                Instruction::BitwiseXor(Reg(0xA), Reg(0xB)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[0xA], 0b100010);
     }
 
@@ -508,33 +1323,39 @@ mod tests {
     #[test_case(75, 240 => (59, 1); "when result overflows")]
     fn inc_reg_by_reg(x_value: u8, y_value: u8) -> (u8, u8) {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(x_value)),
-            Instruction::SetRegToConst(Reg(Y), Const(y_value)),
-            Instruction::IncRegByReg(Reg(X), Reg(Y)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(x_value)),
+                Instruction::SetRegToConst(Reg(Y), Const(y_value)),
+                Instruction::IncRegByReg(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
         (emulator.registers[X as usize], emulator.registers[0xF])
     }
 
     #[test]
     fn dec_reg_by_reg() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(10)),
-            Instruction::SetRegToConst(Reg(Y), Const(7)),
-            Instruction::DecRegByReg(Reg(X), Reg(Y)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(10)),
+                Instruction::SetRegToConst(Reg(Y), Const(7)),
+                Instruction::DecRegByReg(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], 3);
     }
 
     #[test]
     fn dec_reg_by_reg_underflow() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(5)),
-            Instruction::SetRegToConst(Reg(Y), Const(45)),
-            Instruction::DecRegByReg(Reg(X), Reg(Y)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(5)),
+                Instruction::SetRegToConst(Reg(Y), Const(45)),
+                Instruction::DecRegByReg(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], 216);
     }
 
@@ -543,27 +1364,53 @@ mod tests {
         let mut emulator = Emulator::dummy();
 
         let value = 0b00001011;
-        emulator.execute_single(Instruction::SetRegToConst(Reg(X), Const(value)));
+        emulator
+            .execute_single(Instruction::SetRegToConst(Reg(X), Const(value)))
+            .unwrap();
 
-        emulator.execute_single(Instruction::BitshiftRight(Reg(X)));
+        emulator
+            .execute_single(Instruction::BitshiftRight(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value >> 1);
         assert_eq!(emulator.registers[0xF], 1);
-        emulator.execute_single(Instruction::BitshiftRight(Reg(X)));
+        emulator
+            .execute_single(Instruction::BitshiftRight(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value >> 2);
         assert_eq!(emulator.registers[0xF], 1);
-        emulator.execute_single(Instruction::BitshiftRight(Reg(X)));
+        emulator
+            .execute_single(Instruction::BitshiftRight(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value >> 3);
         assert_eq!(emulator.registers[0xF], 0);
     }
 
+    #[test]
+    fn bitshift_right_with_shift_quirk() {
+        let mut emulator =
+            Emulator::with_quirks(DummyInput, DummyOutput::new(), Quirks::COSMAC_VIP);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(0xFF)),
+                Instruction::SetRegToConst(Reg(Y), Const(0b00000110)),
+                Instruction::BitshiftRight(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.registers[X as usize], 0b00000011);
+        assert_eq!(emulator.registers[Y as usize], 0b00000110);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
     #[test]
     fn set_vx_vy_minus_vx() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(12)),
-            Instruction::SetRegToConst(Reg(Y), Const(14)),
-            Instruction::SetVxVyMinusVx(Reg(X), Reg(Y)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(12)),
+                Instruction::SetRegToConst(Reg(Y), Const(14)),
+                Instruction::SetVxVyMinusVx(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], 2);
         assert_eq!(emulator.registers[0xF], 1);
     }
@@ -571,11 +1418,13 @@ mod tests {
     #[test]
     fn set_vx_vy_minus_vx_borrow() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(20)),
-            Instruction::SetRegToConst(Reg(Y), Const(14)),
-            Instruction::SetVxVyMinusVx(Reg(X), Reg(Y)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(20)),
+                Instruction::SetRegToConst(Reg(Y), Const(14)),
+                Instruction::SetVxVyMinusVx(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], 250);
         assert_eq!(emulator.registers[0xF], 0);
     }
@@ -585,37 +1434,78 @@ mod tests {
         let mut emulator = Emulator::dummy();
 
         let value = 0b10110111;
-        emulator.execute_single(Instruction::SetRegToConst(Reg(X), Const(value)));
+        emulator
+            .execute_single(Instruction::SetRegToConst(Reg(X), Const(value)))
+            .unwrap();
 
-        emulator.execute_single(Instruction::BitshiftLeft(Reg(X)));
+        emulator
+            .execute_single(Instruction::BitshiftLeft(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value << 1);
         assert_eq!(emulator.registers[0xF], 1);
-        emulator.execute_single(Instruction::BitshiftLeft(Reg(X)));
+        emulator
+            .execute_single(Instruction::BitshiftLeft(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value << 2);
         assert_eq!(emulator.registers[0xF], 0);
-        emulator.execute_single(Instruction::BitshiftLeft(Reg(X)));
+        emulator
+            .execute_single(Instruction::BitshiftLeft(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[X as usize], value << 3);
         assert_eq!(emulator.registers[0xF], 1);
     }
 
+    #[test]
+    fn bitshift_left_with_shift_quirk() {
+        let mut emulator =
+            Emulator::with_quirks(DummyInput, DummyOutput::new(), Quirks::COSMAC_VIP);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(Y), Const(0b10000001)),
+                Instruction::BitshiftLeft(Reg(X), Reg(Y)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.registers[X as usize], 0b00000010);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test_case(Quirks::default() => 1; "keeps vf by default")]
+    #[test_case(Quirks::COSMAC_VIP => 0; "resets vf with quirk")]
+    fn bitwise_or_with_logic_quirk(quirks: Quirks) -> u8 {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+        emulator.registers[0xF] = 1;
+        emulator
+            .execute_single(Instruction::BitwiseOr(Reg(X), Reg(Y)))
+            .unwrap();
+        emulator.registers[0xF]
+    }
+
     #[test]
     fn if_reg_neq_reg() {
         let mut emulator = Emulator::dummy();
 
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(3)),
-            Instruction::SetRegToConst(Reg(Y), Const(5)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(3)),
+                Instruction::SetRegToConst(Reg(Y), Const(5)),
+            ])
+            .unwrap();
 
         // Should skip instruction
         assert_eq!(emulator.program_counter, 0x204);
-        emulator.execute_single(Instruction::IfRegNeqReg(Reg(X), Reg(Y)));
+        emulator
+            .execute_single(Instruction::IfRegNeqReg(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x208);
 
         // Should not skip instruction
-        emulator.execute_single(Instruction::SetRegToConst(Reg(Y), Const(3)));
+        emulator
+            .execute_single(Instruction::SetRegToConst(Reg(Y), Const(3)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x20A);
-        emulator.execute_single(Instruction::IfRegNeqReg(Reg(X), Reg(Y)));
+        emulator
+            .execute_single(Instruction::IfRegNeqReg(Reg(X), Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x20C);
     }
 
@@ -623,7 +1513,9 @@ mod tests {
     fn set_i() {
         let mut emulator = Emulator::dummy();
         assert_eq!(emulator.i, 0x0);
-        emulator.execute_single(Instruction::SetI(Addr(0x232)));
+        emulator
+            .execute_single(Instruction::SetI(Addr(0x232)))
+            .unwrap();
         assert_eq!(emulator.i, 0x232);
     }
 
@@ -632,18 +1524,33 @@ mod tests {
         let mut emulator = Emulator::dummy();
         let v0 = 7;
         let addr = 0x400;
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(0x0), Const(v0)),
-            Instruction::SetPcToV0PlusAddr(Addr(addr)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(0x0), Const(v0)),
+                Instruction::SetPcToV0PlusAddr(Addr(addr)),
+            ])
+            .unwrap();
         assert_eq!(emulator.program_counter, v0 as u16 + addr);
     }
 
+    #[test]
+    fn set_pc_to_vx_plus_addr_with_jump_quirk() {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), Quirks::CHIP_48);
+        emulator.registers[0] = 1;
+        emulator.registers[4] = 7;
+        emulator
+            .execute_single(Instruction::SetPcToV0PlusAddr(Addr(0x400)))
+            .unwrap();
+        assert_eq!(emulator.program_counter, 0x407);
+    }
+
     #[test]
     fn set_vx_rand() {
         let mut emulator = Emulator::dummy();
         for _ in 0..10_000 {
-            emulator.execute_single(Instruction::SetVxRand(Reg(X), Const(0x0F)));
+            emulator
+                .execute_single(Instruction::SetVxRand(Reg(X), Const(0x0F)))
+                .unwrap();
             let value = emulator.registers[X as usize];
             assert!(value < 2u8.pow(4));
         }
@@ -654,12 +1561,14 @@ mod tests {
         let mut emulator = Emulator::dummy();
         let program = [0b01001111, 0b01111001, 0b00101011, 0b01010110];
         emulator.load(&program);
-        emulator.execute_many(&[
-            Instruction::SetI(Addr(0x200)),
-            Instruction::SetRegToConst(Reg(X), Const(0)),
-            Instruction::SetRegToConst(Reg(Y), Const(0)),
-            Instruction::Draw(Reg(X), Reg(Y), Const(program.len() as u8)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetI(Addr(0x200)),
+                Instruction::SetRegToConst(Reg(X), Const(0)),
+                Instruction::SetRegToConst(Reg(Y), Const(0)),
+                Instruction::Draw(Reg(X), Reg(Y), Const(program.len() as u8)),
+            ])
+            .unwrap();
         for (h, row) in program.iter().enumerate() {
             for w in 0..8 {
                 assert_eq!(emulator.output.get(w, h), (row >> (7 - w)) & 1);
@@ -667,15 +1576,202 @@ mod tests {
         }
     }
 
+    #[test_case(Quirks::default() => 1; "wraps by default")]
+    #[test_case(Quirks::COSMAC_VIP => 0; "clips with quirk")]
+    fn draw_past_edge(quirks: Quirks) -> u8 {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+        emulator.load(&[0xFF]);
+        emulator
+            .execute_many(&[
+                Instruction::SetI(Addr(0x200)),
+                Instruction::SetRegToConst(Reg(X), Const(SCREEN_WIDTH as u8 - 4)),
+                Instruction::SetRegToConst(Reg(Y), Const(SCREEN_HEIGHT as u8 + 1)),
+                Instruction::Draw(Reg(X), Reg(Y), Const(1)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.output.get(SCREEN_WIDTH - 1, 1), 1);
+        emulator.output.get(0, 1)
+    }
+
+    #[test]
+    fn draw_big_sprite_in_high_res() {
+        let mut emulator = Emulator::dummy();
+        let sprite: Vec<u8> = (0..32).map(|n| n * 7).collect();
+        emulator.load(&sprite);
+        emulator
+            .execute_many(&[
+                Instruction::HighRes,
+                Instruction::SetI(Addr(0x200)),
+                Instruction::SetRegToConst(Reg(X), Const(100)),
+                Instruction::SetRegToConst(Reg(Y), Const(40)),
+                Instruction::Draw(Reg(X), Reg(Y), Const(0)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.screen_size(), (128, 64));
+        for (h, row) in sprite.chunks(2).enumerate() {
+            let row = (row[0] as u16) << 8 | row[1] as u16;
+            for w in 0..16 {
+                assert_eq!(
+                    emulator.output.get(100 + w, 40 + h),
+                    (row >> (15 - w)) as u8 & 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_down() {
+        let mut emulator = Emulator::dummy();
+        emulator.output.set(3, 0, 1);
+        emulator.output.set(3, 30, 1);
+        emulator
+            .execute_single(Instruction::ScrollDown(Const(2)))
+            .unwrap();
+        assert_eq!(emulator.output.get(3, 0), 0);
+        assert_eq!(emulator.output.get(3, 2), 1);
+        assert_eq!(emulator.output.get(3, 30), 0);
+    }
+
+    #[test]
+    fn scroll_left_and_right() {
+        let mut emulator = Emulator::dummy();
+        emulator.output.set(10, 5, 1);
+        emulator.execute_single(Instruction::ScrollLeft).unwrap();
+        assert_eq!(emulator.output.get(10, 5), 0);
+        assert_eq!(emulator.output.get(6, 5), 1);
+        emulator.execute_single(Instruction::ScrollRight).unwrap();
+        emulator.execute_single(Instruction::ScrollRight).unwrap();
+        assert_eq!(emulator.output.get(6, 5), 0);
+        assert_eq!(emulator.output.get(14, 5), 1);
+    }
+
+    #[test]
+    fn exit_stops_execution() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x00, 0xFD, 0x6A, 0x01]);
+        emulator.step().unwrap();
+        assert!(emulator.has_exited());
+        emulator.step().unwrap();
+        assert_eq!(emulator.registers[X as usize], 0);
+    }
+
+    #[test]
+    fn low_res_after_high_res() {
+        let mut emulator = Emulator::dummy();
+        emulator.execute_single(Instruction::HighRes).unwrap();
+        assert!(emulator.is_high_res());
+        emulator.execute_single(Instruction::LowRes).unwrap();
+        assert!(!emulator.is_high_res());
+        assert_eq!(emulator.screen_size(), (64, 32));
+    }
+
+    #[test]
+    fn draw_to_both_planes() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0b11000000, 0b10100000]);
+        emulator
+            .execute_many(&[
+                Instruction::SelectPlanes(Const(3)),
+                Instruction::SetI(Addr(0x200)),
+                Instruction::Draw(Reg(X), Reg(Y), Const(1)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.output.get(0, 0), 3);
+        assert_eq!(emulator.output.get(1, 0), 1);
+        assert_eq!(emulator.output.get(2, 0), 2);
+        assert_eq!(emulator.registers[0xF], 0);
+
+        // Drawing to the second plane only collides there
+        emulator
+            .execute_many(&[
+                Instruction::SelectPlanes(Const(2)),
+                Instruction::SetI(Addr(0x201)),
+                Instruction::Draw(Reg(X), Reg(Y), Const(1)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.output.get(0, 0), 1);
+        assert_eq!(emulator.output.get(2, 0), 0);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn clear_screen_only_clears_selected_planes() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0xFF, 0xFF]);
+        emulator
+            .execute_many(&[
+                Instruction::SelectPlanes(Const(3)),
+                Instruction::SetI(Addr(0x200)),
+                Instruction::Draw(Reg(X), Reg(Y), Const(1)),
+                Instruction::SelectPlanes(Const(1)),
+                Instruction::ClearScreen,
+            ])
+            .unwrap();
+        assert_eq!(emulator.output.get(0, 0), 2);
+    }
+
+    #[test]
+    fn scroll_only_moves_selected_planes() {
+        let mut emulator = Emulator::dummy();
+        emulator.output.set(0, 0, 3);
+        emulator
+            .execute_many(&[
+                Instruction::SelectPlanes(Const(2)),
+                Instruction::ScrollUp(Const(1)),
+                Instruction::ScrollDown(Const(2)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.output.get(0, 0), 1);
+        assert_eq!(emulator.output.get(0, 2), 0);
+    }
+
     /// Input that always presses a given key.
     struct ConstantInput(u8);
     impl EmulatorInput for ConstantInput {
-        fn get_key(&self) -> Option<u8> {
-            Some(self.0)
+        fn pressed_keys(&self) -> u16 {
+            1 << self.0
+        }
+        fn poll_event(&mut self) -> Option<KeyEvent> {
+            None
         }
-        fn get_key_blocking(&self) -> u8 {
+    }
+
+    /// Input that always holds down the keys in a bitmask.
+    struct HeldKeys(u16);
+    impl EmulatorInput for HeldKeys {
+        fn pressed_keys(&self) -> u16 {
             self.0
         }
+        fn poll_event(&mut self) -> Option<KeyEvent> {
+            None
+        }
+    }
+
+    /// Input that reports a queue of key events.
+    #[derive(Default)]
+    struct KeyEvents(std::collections::VecDeque<KeyEvent>);
+    impl EmulatorInput for KeyEvents {
+        fn pressed_keys(&self) -> u16 {
+            0
+        }
+        fn poll_event(&mut self) -> Option<KeyEvent> {
+            self.0.pop_front()
+        }
+    }
+
+    #[test_case(2 => 0x206; "first held key")]
+    #[test_case(8 => 0x206; "second held key")]
+    #[test_case(5 => 0x204; "key that is not held")]
+    #[test_case(0x12 => 0x204; "value that is not a key")]
+    fn if_key_eq_vx_sees_every_held_key(key: u8) -> u16 {
+        let mut emulator = Emulator::new(HeldKeys(1 << 2 | 1 << 8), DummyOutput::new());
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(key)),
+                Instruction::IfKeyEqVx(Reg(X)),
+            ])
+            .unwrap();
+        emulator.program_counter
     }
 
     #[test]
@@ -683,12 +1779,16 @@ mod tests {
         let mut emulator = Emulator::new(ConstantInput(0), DummyOutput::new());
 
         // Skip since both are 0
-        emulator.execute_single(Instruction::IfKeyEqVx(Reg(X)));
+        emulator
+            .execute_single(Instruction::IfKeyEqVx(Reg(X)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x204);
 
         // Don't skip. Input is 0, Vx is 5
         emulator.registers[0xA] = 5;
-        emulator.execute_single(Instruction::IfKeyEqVx(Reg(X)));
+        emulator
+            .execute_single(Instruction::IfKeyEqVx(Reg(X)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x206);
     }
 
@@ -697,63 +1797,154 @@ mod tests {
         let mut emulator = Emulator::new(ConstantInput(0), DummyOutput::new());
 
         // Don't skip since both are 0
-        emulator.execute_single(Instruction::IfKeyNeqVx(Reg(X)));
+        emulator
+            .execute_single(Instruction::IfKeyNeqVx(Reg(X)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x202);
 
         // Skip since they are different
         emulator.registers[0xA] = 5;
-        emulator.execute_single(Instruction::IfKeyNeqVx(Reg(X)));
+        emulator
+            .execute_single(Instruction::IfKeyNeqVx(Reg(X)))
+            .unwrap();
         assert_eq!(emulator.program_counter, 0x206);
     }
 
     #[test]
     fn set_reg_to_delay_timer() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(123)),
-            Instruction::SetDelayTimerToReg(Reg(X)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(123)),
+                Instruction::SetDelayTimerToReg(Reg(X)),
+            ])
+            .unwrap();
         assert_eq!(emulator.delay_timer, 123);
-        emulator.execute_single(Instruction::SetRegToDelayTimer(Reg(Y)));
-        // Decreases one in previous step
+        emulator
+            .execute_single(Instruction::SetRegToDelayTimer(Reg(Y)))
+            .unwrap();
+        assert_eq!(emulator.registers[Y as usize], 123);
+        emulator.tick_timers();
+        emulator
+            .execute_single(Instruction::SetRegToDelayTimer(Reg(Y)))
+            .unwrap();
         assert_eq!(emulator.registers[Y as usize], 122);
     }
 
     #[test]
-    fn set_reg_to_get_key() {
-        let mut emulator = Emulator::new(ConstantInput(9), DummyOutput::new());
-        emulator.execute_single(Instruction::SetRegToGetKey(Reg(X)));
+    fn run_frame_ticks_timers_once() {
+        let mut emulator = Emulator::dummy();
+        emulator.delay_timer = 10;
+        emulator.sound_timer = 1;
+        emulator.load(&[0x12, 0x00]); // Loop forever
+        emulator.run_frame(100).unwrap();
+        assert_eq!(emulator.delay_timer, 9);
+        assert_eq!(emulator.sound_timer, 0);
+        emulator.run_frame(100).unwrap();
+        assert_eq!(emulator.delay_timer, 8);
+        assert_eq!(emulator.sound_timer, 0);
+    }
+
+    #[test_case(Quirks::default() => 0x208; "runs all cycles by default")]
+    #[test_case(Quirks::COSMAC_VIP => 0x204; "stops after draw with display wait")]
+    fn run_frame_with_display_wait(quirks: Quirks) -> u16 {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+        emulator.load(&[0x60, 0x00, 0xD0, 0x01, 0x60, 0x00, 0x60, 0x00]);
+        emulator.run_frame(4).unwrap();
+        emulator.program_counter
+    }
+
+    #[test]
+    fn set_reg_to_get_key_waits_for_press_and_release() {
+        let mut emulator = Emulator::new(KeyEvents::default(), DummyOutput::new());
+        let events = [
+            None,
+            Some(KeyEvent::Released(3)),
+            Some(KeyEvent::Pressed(9)),
+            Some(KeyEvent::Released(4)),
+        ];
+        for event in events.iter() {
+            emulator.input.0.extend(event);
+            emulator
+                .execute_single(Instruction::SetRegToGetKey(Reg(X)))
+                .unwrap();
+            assert_eq!(emulator.program_counter, PC_START);
+            assert!(emulator.is_waiting_for_key());
+        }
+
+        emulator.input.0.push_back(KeyEvent::Released(9));
+        emulator
+            .execute_single(Instruction::SetRegToGetKey(Reg(X)))
+            .unwrap();
+        assert_eq!(emulator.program_counter, PC_START + 2);
         assert_eq!(emulator.registers[X as usize], 9);
+        assert!(!emulator.is_waiting_for_key());
+    }
+
+    #[test]
+    fn timers_tick_while_waiting_for_key() {
+        let mut emulator = Emulator::new(KeyEvents::default(), DummyOutput::new());
+        let program = [
+            Instruction::SetRegToGetKey(Reg(X)),
+            Instruction::Goto(Addr(PC_START + 2)),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.to_bytes()).collect();
+        emulator.load(&bytes);
+        emulator.delay_timer = 10;
+
+        // Events from before the wait don't count
+        emulator.input.0.push_back(KeyEvent::Pressed(1));
+        emulator.input.0.push_back(KeyEvent::Released(1));
+        emulator.end_frame();
+        for _ in 0..4 {
+            emulator.run_frame(10).unwrap();
+        }
+        assert_eq!(emulator.delay_timer, 5);
+        assert_eq!(emulator.program_counter, PC_START);
+
+        // But a key can be pressed in one frame and released in another
+        emulator.input.0.push_back(KeyEvent::Pressed(2));
+        emulator.run_frame(10).unwrap();
+        emulator.input.0.push_back(KeyEvent::Released(2));
+        emulator.run_frame(10).unwrap();
+        assert_eq!(emulator.program_counter, PC_START + 2);
+        assert_eq!(emulator.registers[X as usize], 2);
     }
 
     #[test]
     fn set_delay_timer() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(97)),
-            Instruction::SetDelayTimerToReg(Reg(X)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(97)),
+                Instruction::SetDelayTimerToReg(Reg(X)),
+            ])
+            .unwrap();
         assert_eq!(emulator.delay_timer, 97);
     }
 
     #[test]
     fn set_sound_timer() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(97)),
-            Instruction::SetSoundTimerToReg(Reg(X)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(97)),
+                Instruction::SetSoundTimerToReg(Reg(X)),
+            ])
+            .unwrap();
         assert_eq!(emulator.sound_timer, 97);
     }
 
     #[test]
     fn add_reg_to_i() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(32)),
-            Instruction::SetI(Addr(32)),
-            Instruction::AddRegToI(Reg(X)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(32)),
+                Instruction::SetI(Addr(32)),
+                Instruction::AddRegToI(Reg(X)),
+            ])
+            .unwrap();
         assert_eq!(emulator.i, 64);
     }
 
@@ -761,37 +1952,136 @@ mod tests {
     fn set_i_to_sprite_addr_vx() {
         let mut emulator = Emulator::dummy();
         let sprite_no = 8;
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(sprite_no)),
-            Instruction::SetIToSpriteAddrVx(Reg(X)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(sprite_no)),
+                Instruction::SetIToSpriteAddrVx(Reg(X)),
+            ])
+            .unwrap();
         assert_eq!(emulator.i, 5 * sprite_no as u16); // Each sprite is 5 bytes wide
     }
 
+    #[test]
+    fn set_i_to_sprite_addr_vx_ignores_high_nibble() {
+        let mut emulator = Emulator::dummy();
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(0xF3)),
+                Instruction::SetIToSpriteAddrVx(Reg(X)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.i, 5 * 3);
+    }
+
+    #[test]
+    fn set_i_to_big_sprite_addr_vx() {
+        let mut emulator = Emulator::dummy();
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(8)),
+                Instruction::SetIToBigSpriteAddrVx(Reg(X)),
+            ])
+            .unwrap();
+        let i = emulator.i as usize;
+        assert_eq!(emulator.memory[i..i + 10], BIG_FONT[80..90]);
+    }
+
     #[test]
     fn set_i_to_bcd_of_reg() {
         let mut emulator = Emulator::dummy();
-        emulator.execute_many(&[
-            Instruction::SetRegToConst(Reg(X), Const(184)),
-            Instruction::SetIToBcdOfReg(Reg(X)),
-        ]);
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(184)),
+                Instruction::SetIToBcdOfReg(Reg(X)),
+            ])
+            .unwrap();
         assert_eq!(emulator.memory[emulator.i as usize], 1);
         assert_eq!(emulator.memory[emulator.i as usize + 1], 8);
         assert_eq!(emulator.memory[emulator.i as usize + 2], 4);
     }
 
+    /// A writer that can be read from after being handed to a tracer.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_has_a_line_per_instruction() {
+        let buffer = SharedBuffer::default();
+        let mut emulator = Emulator::dummy();
+        emulator.set_tracer(Some(Tracer::new(buffer.clone())));
+        emulator.load(&[0x60, 0x12, 0xF0, 0x00, 0x03, 0x45, 0x00, 0x00]);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        assert!(emulator.step().is_err());
+
+        let tracer = emulator.set_tracer(None).unwrap();
+        assert_eq!(tracer.cycles(), 2);
+        let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let registers = format!("12{}", "00".repeat(15));
+        let expected = format!(
+            "000000001 0200 6012     LD V0, 0x12          V={0} I=0000 SP=00 DT=00 ST=00\n\
+             000000002 0202 F0000345 LD I, long 0x0345    V={0} I=0345 SP=00 DT=00 ST=00\n",
+            registers
+        );
+        assert_eq!(trace, expected);
+    }
+
+    #[test]
+    fn memory_accesses_of_last_instruction() {
+        let mut emulator = Emulator::dummy();
+        emulator
+            .execute_many(&[
+                Instruction::SetRegToConst(Reg(X), Const(184)),
+                Instruction::SetI(Addr(0x300)),
+                Instruction::SetIToBcdOfReg(Reg(X)),
+            ])
+            .unwrap();
+        let write = |address, value| MemoryAccess::Write { address, value };
+        assert_eq!(
+            emulator.memory_accesses(),
+            [write(0x302, 4), write(0x301, 8), write(0x300, 1)]
+        );
+
+        emulator
+            .execute_single(Instruction::RegLoad(Reg(0)))
+            .unwrap();
+        let read = MemoryAccess::Read {
+            address: 0x300,
+            value: 1,
+        };
+        assert_eq!(emulator.memory_accesses(), [read]);
+
+        emulator.execute_single(Instruction::ClearScreen).unwrap();
+        assert!(emulator.memory_accesses().is_empty());
+    }
+
     #[test]
     fn reg_dump() {
         let mut emulator = Emulator::dummy();
 
         // Set V0..V0xF to their index
         for i in 0..=0xF {
-            emulator.execute_single(Instruction::SetRegToConst(Reg(i), Const(i)));
+            emulator
+                .execute_single(Instruction::SetRegToConst(Reg(i), Const(i)))
+                .unwrap();
         }
 
         // Dump all up to Vx
-        emulator.execute_single(Instruction::SetI(Addr(0x200)));
-        emulator.execute_single(Instruction::RegDump(Reg(X)));
+        emulator
+            .execute_single(Instruction::SetI(Addr(0x200)))
+            .unwrap();
+        emulator
+            .execute_single(Instruction::RegDump(Reg(X)))
+            .unwrap();
 
         // All register values up to x should be in memory
         for offset in 0..X {
@@ -807,6 +2097,229 @@ mod tests {
         }
     }
 
+    #[test_case(Quirks::default() => 0x300; "leaves i by default")]
+    #[test_case(Quirks::CHIP_48 => 0x300 + X as u16; "increments i by x on chip48")]
+    #[test_case(Quirks::COSMAC_VIP => 0x300 + X as u16 + 1; "increments i with quirk")]
+    fn reg_dump_with_load_store_quirk(quirks: Quirks) -> u16 {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+        emulator
+            .execute_many(&[Instruction::SetI(Addr(0x300)), Instruction::RegDump(Reg(X))])
+            .unwrap();
+        emulator.i
+    }
+
+    #[test_case(Instruction::RegDump(Reg(0)) => 0; "dump")]
+    #[test_case(Instruction::RegLoad(Reg(0)) => 0; "load")]
+    fn load_store_at_end_of_memory_wraps_i(instruction: Instruction) -> u16 {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), Quirks::XO_CHIP);
+        emulator.registers[0] = 0xAB;
+        emulator.memory[0xFFFF] = 0xAB;
+        emulator
+            .execute_many(&[Instruction::SetILong(Addr(0xFFFF)), instruction])
+            .unwrap();
+        assert_eq!(emulator.registers[0], 0xAB);
+        emulator.i
+    }
+
+    #[test]
+    fn save_and_load_flags() {
+        let mut emulator = Emulator::dummy();
+        for i in 0..=0xF {
+            emulator.registers[i] = i as u8 + 1;
+        }
+        emulator
+            .execute_single(Instruction::SaveFlags(Reg(3)))
+            .unwrap();
+        emulator.registers = [0; NUM_REGISTERS];
+        emulator
+            .execute_single(Instruction::LoadFlags(Reg(7)))
+            .unwrap();
+        assert_eq!(emulator.registers[..8], [1, 2, 3, 4, 0, 0, 0, 0]);
+    }
+
+    #[test_case(2, 5 => vec![2, 3, 4, 5]; "ascending")]
+    #[test_case(5, 2 => vec![5, 4, 3, 2]; "descending")]
+    fn save_reg_range(x: u8, y: u8) -> Vec<u8> {
+        let mut emulator = Emulator::dummy();
+        for i in 0..=0xF {
+            emulator.registers[i] = i as u8;
+        }
+        emulator
+            .execute_many(&[
+                Instruction::SetI(Addr(0x300)),
+                Instruction::SaveRegRange(Reg(x), Reg(y)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.i, 0x300);
+        emulator.memory[0x300..0x304].to_vec()
+    }
+
+    #[test]
+    fn load_leaves_out_what_does_not_fit() {
+        let mut emulator = Emulator::dummy();
+        let program = vec![0xAB; 0x2000];
+        emulator.load(&program);
+        assert!(emulator.memory[0x200..].iter().all(|&b| b == 0xAB));
+    }
+
+    /// Inputs to the `run` fuzz target that overflowed I after `FX55` or `FX65` at the end of memory,
+    /// where the first byte picks XO-CHIP and the rest is the program.
+    #[test]
+    fn fuzzed_load_store_at_end_of_memory() {
+        let inputs: [&[u8]; 2] = [
+            // A register dump after pointing I at the last address with a long load
+            &[0x04, 0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x55],
+            // A register load in a loop, moving I 16 bytes further every time
+            &[0x04, 0xFF, 0x65, 0x12],
+        ];
+        for data in inputs.iter() {
+            let (_, quirks) = Quirks::PRESETS[data[0] as usize];
+            let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+            emulator.load(&data[1..]);
+            for _ in 0..10_000 {
+                // Faults are fine, as long as they don't panic
+                if emulator.step().is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn load_reg_range() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[7, 8, 9]);
+        emulator
+            .execute_many(&[
+                Instruction::SetI(Addr(0x200)),
+                Instruction::LoadRegRange(Reg(3), Reg(1)),
+            ])
+            .unwrap();
+        assert_eq!(emulator.registers[..4], [0, 9, 8, 7]);
+    }
+
+    #[test]
+    fn long_set_i_addresses_all_xo_chip_memory() {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), Quirks::XO_CHIP);
+        emulator.load(&[0xF0, 0x00, 0xFF, 0xF0, 0xFA, 0x55]);
+        emulator.registers[X as usize] = 42;
+        emulator.step().unwrap();
+        assert_eq!(emulator.i, 0xFFF0);
+        assert_eq!(emulator.program_counter, 0x204);
+        emulator.step().unwrap();
+        assert_eq!(emulator.memory[0xFFF0 + X as usize], 42);
+    }
+
+    #[test]
+    fn skip_over_long_set_i() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x3A, 0x00, 0xF0, 0x00, 0x12, 0x34]);
+        emulator.step().unwrap();
+        assert_eq!(emulator.program_counter, 0x206);
+    }
+
+    #[test]
+    fn load_audio_pattern_and_pitch() {
+        let mut emulator = Emulator::dummy();
+        let pattern: Vec<u8> = (0..16).collect();
+        emulator.load(&pattern);
+        emulator.registers[X as usize] = 100;
+        emulator
+            .execute_many(&[
+                Instruction::SetI(Addr(0x200)),
+                Instruction::LoadAudioPattern,
+                Instruction::SetPitchToReg(Reg(X)),
+            ])
+            .unwrap();
+        let (loaded, pitch) = emulator.audio_pattern();
+        assert_eq!(loaded[..], pattern[..]);
+        assert_eq!(pitch, 100);
+    }
+
+    #[derive(Default)]
+    struct BuzzerLog(Vec<bool>);
+
+    impl EmulatorAudio for BuzzerLog {
+        fn set_buzzer(&mut self, on: bool) {
+            self.0.push(on);
+        }
+    }
+
+    #[test]
+    fn sound_timer_sounds_buzzer() {
+        let mut emulator = Emulator::with_audio(
+            DummyInput,
+            DummyOutput::new(),
+            BuzzerLog::default(),
+            Quirks::default(),
+        );
+        emulator.registers[X as usize] = 2;
+        emulator
+            .execute_single(Instruction::SetSoundTimerToReg(Reg(X)))
+            .unwrap();
+        for _ in 0..4 {
+            emulator.tick_timers();
+        }
+        assert_eq!(emulator.audio().0, [true, true, false, false]);
+    }
+
+    #[test]
+    fn restore_keeps_buzzer_without_audio_pattern() {
+        let mut emulator = Emulator::with_audio(
+            DummyInput,
+            DummyOutput::new(),
+            PcmAudio::new(6000),
+            Quirks::XO_CHIP,
+        );
+        let snapshot = emulator.snapshot();
+        emulator
+            .execute_many(&[
+                Instruction::SetI(Addr(0x300)),
+                Instruction::LoadAudioPattern,
+            ])
+            .unwrap();
+        emulator.restore(&snapshot).unwrap();
+
+        // An empty pattern would hold the samples at a constant level
+        emulator.sound_timer = 1;
+        emulator.tick_timers();
+        let samples = emulator.audio().samples();
+        assert!(samples.iter().any(|&sample| sample > 0));
+        assert!(samples.iter().any(|&sample| sample < 0));
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x00]);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        let snapshot = emulator.snapshot();
+
+        // Clear the screen and keep running
+        emulator.output.clear();
+        emulator.step().unwrap();
+        emulator.registers[0] = 0;
+        assert_ne!(snapshot, emulator.snapshot());
+
+        emulator.restore(&snapshot).unwrap();
+        assert_eq!(snapshot, emulator.snapshot());
+        assert_eq!(emulator.output.get(5, 5), 1);
+    }
+
+    #[test]
+    fn restore_snapshot_of_other_program_fails() {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x00, 0xE0]);
+        let snapshot = emulator.snapshot();
+        emulator.load(&[0x00, 0xE0, 0x00, 0xE0]);
+        assert!(matches!(
+            emulator.restore(&snapshot),
+            Err(SnapshotError::RomMismatch { .. })
+        ));
+    }
+
     #[test]
     fn reg_load() {
         let mut emulator = Emulator::dummy();
@@ -819,8 +2332,12 @@ mod tests {
         emulator.load(&data);
 
         // Load into registers
-        emulator.execute_single(Instruction::SetI(Addr(0x200)));
-        emulator.execute_single(Instruction::RegLoad(Reg(X)));
+        emulator
+            .execute_single(Instruction::SetI(Addr(0x200)))
+            .unwrap();
+        emulator
+            .execute_single(Instruction::RegLoad(Reg(X)))
+            .unwrap();
 
         // The others should not have been dumped
         for offset in 0..=X {
@@ -0,0 +1,78 @@
+use crate::emulator::instruction::{DecodeError, Instruction};
+use std::fmt;
+
+/// A runtime error caused by the program being executed.
+/// Every fault carries the program counter of the offending instruction.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Fault {
+    /// The opcode at the program counter is not a known instruction.
+    InvalidOpcode(DecodeError),
+    /// The program counter points past the end of memory.
+    PcOutOfBounds { pc: u16 },
+    /// A `Call` was made with a full stack.
+    StackOverflow { pc: u16, instruction: Instruction },
+    /// A `Return` was made with an empty stack.
+    StackUnderflow { pc: u16, instruction: Instruction },
+    /// An instruction accessed memory starting at `address` that does not fit in memory.
+    MemoryOutOfBounds {
+        pc: u16,
+        instruction: Instruction,
+        address: u16,
+    },
+    /// `AddRegToI` made the I register overflow.
+    IOverflow { pc: u16, instruction: Instruction },
+}
+
+impl Fault {
+    /// The address of the instruction that caused the fault.
+    pub fn pc(&self) -> u16 {
+        match *self {
+            Fault::InvalidOpcode(DecodeError { address, .. }) => address.unwrap_or_default(),
+            Fault::PcOutOfBounds { pc }
+            | Fault::StackOverflow { pc, .. }
+            | Fault::StackUnderflow { pc, .. }
+            | Fault::MemoryOutOfBounds { pc, .. }
+            | Fault::IOverflow { pc, .. } => pc,
+        }
+    }
+
+    /// The instruction that caused the fault, if it could be decoded.
+    pub fn instruction(&self) -> Option<Instruction> {
+        match *self {
+            Fault::InvalidOpcode(_) | Fault::PcOutOfBounds { .. } => None,
+            Fault::StackOverflow { instruction, .. }
+            | Fault::StackUnderflow { instruction, .. }
+            | Fault::MemoryOutOfBounds { instruction, .. }
+            | Fault::IOverflow { instruction, .. } => Some(instruction),
+        }
+    }
+}
+
+impl From<DecodeError> for Fault {
+    fn from(e: DecodeError) -> Self {
+        Fault::InvalidOpcode(e)
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::InvalidOpcode(e) => return write!(f, "{}", e),
+            Fault::PcOutOfBounds { pc } => {
+                return write!(f, "program counter {:#05x} is out of bounds", pc)
+            }
+            Fault::StackOverflow { .. } => write!(f, "stack overflow")?,
+            Fault::StackUnderflow { .. } => write!(f, "return with empty stack")?,
+            Fault::MemoryOutOfBounds { address, .. } => {
+                write!(f, "memory access at {:#05x} is out of bounds", address)?
+            }
+            Fault::IOverflow { .. } => write!(f, "I register overflow")?,
+        }
+        if let Some(instruction) = self.instruction() {
+            write!(f, " in {}", instruction)?;
+        }
+        write!(f, " at {:#05x}", self.pc())
+    }
+}
+
+impl std::error::Error for Fault {}
@@ -1,17 +1,36 @@
-/// Represents an input device that is capable of providing keys in the range 0..0xF.
+/// A key being pressed or released, with keys in the range 0..=0xF.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+}
+
+/// Represents an input device with the keys 0..=0xF, any number of which can be held down at once.
 pub trait EmulatorInput {
-    fn get_key(&self) -> Option<u8>;
-    fn get_key_blocking(&self) -> u8;
+    /// The keys that are currently held down, with bit `n` set while key `n` is.
+    fn pressed_keys(&self) -> u16;
+
+    /// Take the oldest key press or release that has not been taken yet.
+    /// Used by `FX0A`, which waits for a key to be pressed and released.
+    fn poll_event(&mut self) -> Option<KeyEvent>;
+
+    /// Called by the emulator at the end of every frame.
+    fn end_frame(&mut self) {}
+
+    /// Whether `key` is currently held down.
+    fn is_pressed(&self, key: u8) -> bool {
+        key <= 0xF && self.pressed_keys() & 1 << key != 0
+    }
 }
 
 /// An input device that never provides any input
 pub struct DummyInput;
 
 impl EmulatorInput for DummyInput {
-    fn get_key(&self) -> Option<u8> {
-        None
-    }
-    fn get_key_blocking(&self) -> u8 {
+    fn pressed_keys(&self) -> u16 {
         0
     }
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        None
+    }
 }
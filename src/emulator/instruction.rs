@@ -1,4 +1,6 @@
 use crate::util::bit_splitter::BitSplitter;
+use std::convert::TryFrom;
+use std::fmt;
 
 /// A wrapper for addresses.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -12,7 +14,8 @@ pub struct Reg(pub u8);
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub struct Const(pub u8);
 
-/// A single instruction from the CHIP-8 instruction set.
+/// A single instruction from the CHIP-8 instruction set,
+/// including the SUPER-CHIP 1.1 and XO-CHIP extensions.
 /// Two bytes written in hexadecimal, with the following special characters:
 /// - NNN: address
 /// - NN: 8-bit constant
@@ -23,10 +26,24 @@ pub struct Const(pub u8);
 /// - VN: One of the 16 available variables (register identifiers)
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Instruction {
+    /// 00CN (SUPER-CHIP)
+    ScrollDown(Const),
+    /// 00DN (XO-CHIP)
+    ScrollUp(Const),
     /// 00E0
     ClearScreen,
     /// 00EE
     Return,
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP)
+    Exit,
+    /// 00FE (SUPER-CHIP)
+    LowRes,
+    /// 00FF (SUPER-CHIP)
+    HighRes,
     /// 1NNN
     Goto(Addr),
     /// 2NNN
@@ -37,6 +54,10 @@ pub enum Instruction {
     IfRegNeqConst(Reg, Const),
     /// 5XY0
     IfRegEqReg(Reg, Reg),
+    /// 5XY2 (XO-CHIP)
+    SaveRegRange(Reg, Reg),
+    /// 5XY3 (XO-CHIP)
+    LoadRegRange(Reg, Reg),
     /// 6XNN
     SetRegToConst(Reg, Const),
     /// 7XNN
@@ -54,11 +75,11 @@ pub enum Instruction {
     /// 8XY5
     DecRegByReg(Reg, Reg),
     /// 8XY6
-    BitshiftRight(Reg),
+    BitshiftRight(Reg, Reg),
     /// 8XY7
     SetVxVyMinusVx(Reg, Reg),
     /// 8XYE
-    BitshiftLeft(Reg),
+    BitshiftLeft(Reg, Reg),
     /// 9XY0
     IfRegNeqReg(Reg, Reg),
     /// ANNN
@@ -67,12 +88,18 @@ pub enum Instruction {
     SetPcToV0PlusAddr(Addr),
     /// CXNN
     SetVxRand(Reg, Const),
-    /// DXYN
+    /// DXYN, where N = 0 draws a 16x16 sprite (SUPER-CHIP)
     Draw(Reg, Reg, Const),
     /// EX9E
     IfKeyEqVx(Reg),
     /// EXA1
     IfKeyNeqVx(Reg),
+    /// F000 NNNN (XO-CHIP), the only instruction that is four bytes long
+    SetILong(Addr),
+    /// FN01 (XO-CHIP)
+    SelectPlanes(Const),
+    /// F002 (XO-CHIP)
+    LoadAudioPattern,
     /// FX07
     SetRegToDelayTimer(Reg),
     /// FX0A
@@ -85,14 +112,53 @@ pub enum Instruction {
     AddRegToI(Reg),
     /// FX29
     SetIToSpriteAddrVx(Reg),
+    /// FX30 (SUPER-CHIP)
+    SetIToBigSpriteAddrVx(Reg),
     /// FX33
     SetIToBcdOfReg(Reg),
+    /// FX3A (XO-CHIP)
+    SetPitchToReg(Reg),
     /// FX55
     RegDump(Reg),
     /// FX65
     RegLoad(Reg),
+    /// FX75 (SUPER-CHIP)
+    SaveFlags(Reg),
+    /// FX85 (SUPER-CHIP)
+    LoadFlags(Reg),
 }
 
+/// An opcode that does not correspond to any known instruction.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct DecodeError {
+    /// The raw opcode that could not be decoded.
+    pub opcode: u16,
+    /// The address the opcode was read from, if known.
+    pub address: Option<u16>,
+}
+
+impl DecodeError {
+    /// Attach the address the opcode was read from.
+    pub fn at(self, address: u16) -> DecodeError {
+        DecodeError {
+            address: Some(address),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {:#06x}", self.opcode)?;
+        if let Some(address) = self.address {
+            write!(f, " at {:#05x}", address)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
 impl Instruction {
     fn split_u16(value: u16) -> (u8, u8) {
         let left = (value & 0xFF00) >> 8;
@@ -100,21 +166,59 @@ impl Instruction {
         (left as u8, right as u8)
     }
 
-    pub fn from_u16(value: u16) -> Instruction {
+    /// Decode the instruction at the start of `bytes`,
+    /// which may be four bytes long in the case of `SetILong`.
+    pub fn decode(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+        match *bytes {
+            [0xF0, 0x00, high, low, ..] => Ok(Instruction::SetILong(Addr(
+                BitSplitter::new(high, low).as_u16(),
+            ))),
+            [left, right, ..] => Instruction::from_two_u8(left, right),
+            [left] => Err(DecodeError {
+                opcode: (left as u16) << 8,
+                address: None,
+            }),
+            [] => Err(DecodeError {
+                opcode: 0,
+                address: None,
+            }),
+        }
+    }
+
+    /// The number of bytes the instruction takes up in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::SetILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Decode a two byte instruction.
+    /// Fails on `F000`, which needs the two following bytes as well, see `decode`.
+    pub fn from_u16(value: u16) -> Result<Instruction, DecodeError> {
         let (left, right) = Self::split_u16(value);
         Instruction::from_two_u8(left, right)
     }
 
-    pub fn from_two_u8(left: u8, right: u8) -> Instruction {
+    pub fn from_two_u8(left: u8, right: u8) -> Result<Instruction, DecodeError> {
         let opcode = BitSplitter::new(left, right);
-        match opcode.as_four_u8() {
+        let instruction = match opcode.as_four_u8() {
+            (0, 0, 0xC, n) => Instruction::ScrollDown(Const(n)),
+            (0, 0, 0xD, n) => Instruction::ScrollUp(Const(n)),
             (0, 0, 0xE, 0) => Instruction::ClearScreen,
             (0, 0, 0xE, 0xE) => Instruction::Return,
+            (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0, 0, 0xF, 0xD) => Instruction::Exit,
+            (0, 0, 0xF, 0xE) => Instruction::LowRes,
+            (0, 0, 0xF, 0xF) => Instruction::HighRes,
             (1, _, _, _) => Instruction::Goto(Addr(opcode.last_12_bits())),
             (2, _, _, _) => Instruction::Call(Addr(opcode.last_12_bits())),
             (3, x, _, _) => Instruction::IfRegEqConst(Reg(x), Const(opcode.last_8_bits())),
             (4, x, _, _) => Instruction::IfRegNeqConst(Reg(x), Const(opcode.last_8_bits())),
             (5, x, y, 0) => Instruction::IfRegEqReg(Reg(x), Reg(y)),
+            (5, x, y, 2) => Instruction::SaveRegRange(Reg(x), Reg(y)),
+            (5, x, y, 3) => Instruction::LoadRegRange(Reg(x), Reg(y)),
             (6, x, _, _) => Instruction::SetRegToConst(Reg(x), Const(opcode.last_8_bits())),
             (7, x, _, _) => Instruction::IncRegByConst(Reg(x), Const(opcode.last_8_bits())),
             (8, x, y, 0) => Instruction::SetRegToReg(Reg(x), Reg(y)),
@@ -123,9 +227,9 @@ impl Instruction {
             (8, x, y, 3) => Instruction::BitwiseXor(Reg(x), Reg(y)),
             (8, x, y, 4) => Instruction::IncRegByReg(Reg(x), Reg(y)),
             (8, x, y, 5) => Instruction::DecRegByReg(Reg(x), Reg(y)),
-            (8, x, _, 6) => Instruction::BitshiftRight(Reg(x)),
+            (8, x, y, 6) => Instruction::BitshiftRight(Reg(x), Reg(y)),
             (8, x, y, 7) => Instruction::SetVxVyMinusVx(Reg(x), Reg(y)),
-            (8, x, _, 0xE) => Instruction::BitshiftLeft(Reg(x)),
+            (8, x, y, 0xE) => Instruction::BitshiftLeft(Reg(x), Reg(y)),
             (9, x, y, 0) => Instruction::IfRegNeqReg(Reg(x), Reg(y)),
             (0xA, _, _, _) => Instruction::SetI(Addr(opcode.last_12_bits())),
             (0xB, _, _, _) => Instruction::SetPcToV0PlusAddr(Addr(opcode.last_12_bits())),
@@ -133,154 +237,476 @@ impl Instruction {
             (0xD, x, y, n) => Instruction::Draw(Reg(x), Reg(y), Const(n)),
             (0xE, x, 9, 0xE) => Instruction::IfKeyEqVx(Reg(x)),
             (0xE, x, 0xA, 1) => Instruction::IfKeyNeqVx(Reg(x)),
+            (0xF, n, 0, 1) => Instruction::SelectPlanes(Const(n)),
+            (0xF, 0, 0, 2) => Instruction::LoadAudioPattern,
             (0xF, x, 0, 7) => Instruction::SetRegToDelayTimer(Reg(x)),
             (0xF, x, 0, 0xA) => Instruction::SetRegToGetKey(Reg(x)),
             (0xF, x, 1, 5) => Instruction::SetDelayTimerToReg(Reg(x)),
             (0xF, x, 1, 8) => Instruction::SetSoundTimerToReg(Reg(x)),
             (0xF, x, 1, 0xE) => Instruction::AddRegToI(Reg(x)),
             (0xF, x, 2, 9) => Instruction::SetIToSpriteAddrVx(Reg(x)),
+            (0xF, x, 3, 0) => Instruction::SetIToBigSpriteAddrVx(Reg(x)),
             (0xF, x, 3, 3) => Instruction::SetIToBcdOfReg(Reg(x)),
+            (0xF, x, 3, 0xA) => Instruction::SetPitchToReg(Reg(x)),
             (0xF, x, 5, 5) => Instruction::RegDump(Reg(x)),
             (0xF, x, 6, 5) => Instruction::RegLoad(Reg(x)),
+            (0xF, x, 7, 5) => Instruction::SaveFlags(Reg(x)),
+            (0xF, x, 8, 5) => Instruction::LoadFlags(Reg(x)),
             _ => {
-                // TODO: Use Option?
-                log::error!("Unknown opcode {:#06x}", opcode.as_u16());
-                panic!("Unknown opcode!")
+                return Err(DecodeError {
+                    opcode: opcode.as_u16(),
+                    address: None,
+                })
             }
+        };
+        Ok(instruction)
+    }
+}
+
+impl Instruction {
+    /// Encode the instruction as a two byte opcode, the inverse of `from_u16`.
+    /// For the four byte `SetILong`, this is only the first two bytes, see `to_bytes`.
+    /// Registers, addresses and constants that are too large for the opcode are truncated.
+    pub fn to_u16(&self) -> u16 {
+        let nibbles = |a: u16, x: u8, y: u8, n: u8| {
+            a << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (n as u16 & 0xF)
+        };
+        let address = |a: u16, Addr(nnn)| a << 12 | (nnn & 0xFFF);
+        let constant = |a: u16, Reg(x), Const(nn)| a << 12 | (x as u16 & 0xF) << 8 | nn as u16;
+
+        match *self {
+            Instruction::ScrollDown(Const(n)) => nibbles(0, 0, 0xC, n),
+            Instruction::ScrollUp(Const(n)) => nibbles(0, 0, 0xD, n),
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Goto(addr) => address(1, addr),
+            Instruction::Call(addr) => address(2, addr),
+            Instruction::IfRegEqConst(x, nn) => constant(3, x, nn),
+            Instruction::IfRegNeqConst(x, nn) => constant(4, x, nn),
+            Instruction::IfRegEqReg(Reg(x), Reg(y)) => nibbles(5, x, y, 0),
+            Instruction::SaveRegRange(Reg(x), Reg(y)) => nibbles(5, x, y, 2),
+            Instruction::LoadRegRange(Reg(x), Reg(y)) => nibbles(5, x, y, 3),
+            Instruction::SetRegToConst(x, nn) => constant(6, x, nn),
+            Instruction::IncRegByConst(x, nn) => constant(7, x, nn),
+            Instruction::SetRegToReg(Reg(x), Reg(y)) => nibbles(8, x, y, 0),
+            Instruction::BitwiseOr(Reg(x), Reg(y)) => nibbles(8, x, y, 1),
+            Instruction::BitwiseAnd(Reg(x), Reg(y)) => nibbles(8, x, y, 2),
+            Instruction::BitwiseXor(Reg(x), Reg(y)) => nibbles(8, x, y, 3),
+            Instruction::IncRegByReg(Reg(x), Reg(y)) => nibbles(8, x, y, 4),
+            Instruction::DecRegByReg(Reg(x), Reg(y)) => nibbles(8, x, y, 5),
+            Instruction::BitshiftRight(Reg(x), Reg(y)) => nibbles(8, x, y, 6),
+            Instruction::SetVxVyMinusVx(Reg(x), Reg(y)) => nibbles(8, x, y, 7),
+            Instruction::BitshiftLeft(Reg(x), Reg(y)) => nibbles(8, x, y, 0xE),
+            Instruction::IfRegNeqReg(Reg(x), Reg(y)) => nibbles(9, x, y, 0),
+            Instruction::SetI(addr) => address(0xA, addr),
+            Instruction::SetPcToV0PlusAddr(addr) => address(0xB, addr),
+            Instruction::SetVxRand(x, nn) => constant(0xC, x, nn),
+            Instruction::Draw(Reg(x), Reg(y), Const(n)) => nibbles(0xD, x, y, n),
+            Instruction::IfKeyEqVx(Reg(x)) => nibbles(0xE, x, 9, 0xE),
+            Instruction::IfKeyNeqVx(Reg(x)) => nibbles(0xE, x, 0xA, 1),
+            Instruction::SetILong(_) => 0xF000,
+            Instruction::SelectPlanes(Const(n)) => nibbles(0xF, n, 0, 1),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::SetRegToDelayTimer(Reg(x)) => nibbles(0xF, x, 0, 7),
+            Instruction::SetRegToGetKey(Reg(x)) => nibbles(0xF, x, 0, 0xA),
+            Instruction::SetDelayTimerToReg(Reg(x)) => nibbles(0xF, x, 1, 5),
+            Instruction::SetSoundTimerToReg(Reg(x)) => nibbles(0xF, x, 1, 8),
+            Instruction::AddRegToI(Reg(x)) => nibbles(0xF, x, 1, 0xE),
+            Instruction::SetIToSpriteAddrVx(Reg(x)) => nibbles(0xF, x, 2, 9),
+            Instruction::SetIToBigSpriteAddrVx(Reg(x)) => nibbles(0xF, x, 3, 0),
+            Instruction::SetIToBcdOfReg(Reg(x)) => nibbles(0xF, x, 3, 3),
+            Instruction::SetPitchToReg(Reg(x)) => nibbles(0xF, x, 3, 0xA),
+            Instruction::RegDump(Reg(x)) => nibbles(0xF, x, 5, 5),
+            Instruction::RegLoad(Reg(x)) => nibbles(0xF, x, 6, 5),
+            Instruction::SaveFlags(Reg(x)) => nibbles(0xF, x, 7, 5),
+            Instruction::LoadFlags(Reg(x)) => nibbles(0xF, x, 8, 5),
         }
     }
+
+    /// Encode the instruction as the bytes it takes up in memory, the inverse of `decode`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.to_u16().to_be_bytes().to_vec();
+        if let Instruction::SetILong(Addr(nnnn)) = *self {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#05X}", self.0)
+    }
+}
+
+/// Constants are written in hexadecimal.
+/// The 4-bit constants of `Draw`, the scrolls and `SelectPlanes` are written in decimal.
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04X}", self.0)
+    }
+}
+
+/// The conventional assembly mnemonics from Cowgod's CHIP-8 technical reference,
+/// extended with the common mnemonics for SUPER-CHIP and XO-CHIP.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::ScrollDown(Const(n)) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(Const(n)) => write!(f, "SCU {}", n),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Goto(addr) => write!(f, "JP {}", addr),
+            Instruction::Call(addr) => write!(f, "CALL {}", addr),
+            Instruction::IfRegEqConst(x, nn) => write!(f, "SE {}, {}", x, nn),
+            Instruction::IfRegNeqConst(x, nn) => write!(f, "SNE {}, {}", x, nn),
+            Instruction::IfRegEqReg(x, y) => write!(f, "SE {}, {}", x, y),
+            Instruction::SaveRegRange(x, y) => write!(f, "SAVE {}, {}", x, y),
+            Instruction::LoadRegRange(x, y) => write!(f, "LOAD {}, {}", x, y),
+            Instruction::SetRegToConst(x, nn) => write!(f, "LD {}, {}", x, nn),
+            Instruction::IncRegByConst(x, nn) => write!(f, "ADD {}, {}", x, nn),
+            Instruction::SetRegToReg(x, y) => write!(f, "LD {}, {}", x, y),
+            Instruction::BitwiseOr(x, y) => write!(f, "OR {}, {}", x, y),
+            Instruction::BitwiseAnd(x, y) => write!(f, "AND {}, {}", x, y),
+            Instruction::BitwiseXor(x, y) => write!(f, "XOR {}, {}", x, y),
+            Instruction::IncRegByReg(x, y) => write!(f, "ADD {}, {}", x, y),
+            Instruction::DecRegByReg(x, y) => write!(f, "SUB {}, {}", x, y),
+            Instruction::BitshiftRight(x, y) => write!(f, "SHR {}, {}", x, y),
+            Instruction::SetVxVyMinusVx(x, y) => write!(f, "SUBN {}, {}", x, y),
+            Instruction::BitshiftLeft(x, y) => write!(f, "SHL {}, {}", x, y),
+            Instruction::IfRegNeqReg(x, y) => write!(f, "SNE {}, {}", x, y),
+            Instruction::SetI(addr) => write!(f, "LD I, {}", addr),
+            Instruction::SetPcToV0PlusAddr(addr) => write!(f, "JP V0, {}", addr),
+            Instruction::SetVxRand(x, nn) => write!(f, "RND {}, {}", x, nn),
+            Instruction::Draw(x, y, Const(n)) => write!(f, "DRW {}, {}, {}", x, y, n),
+            Instruction::IfKeyEqVx(x) => write!(f, "SKP {}", x),
+            Instruction::IfKeyNeqVx(x) => write!(f, "SKNP {}", x),
+            Instruction::SetILong(Addr(nnnn)) => write!(f, "LD I, long {:#06X}", nnnn),
+            Instruction::SelectPlanes(Const(n)) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SetRegToDelayTimer(x) => write!(f, "LD {}, DT", x),
+            Instruction::SetRegToGetKey(x) => write!(f, "LD {}, K", x),
+            Instruction::SetDelayTimerToReg(x) => write!(f, "LD DT, {}", x),
+            Instruction::SetSoundTimerToReg(x) => write!(f, "LD ST, {}", x),
+            Instruction::AddRegToI(x) => write!(f, "ADD I, {}", x),
+            Instruction::SetIToSpriteAddrVx(x) => write!(f, "LD F, {}", x),
+            Instruction::SetIToBigSpriteAddrVx(x) => write!(f, "LD HF, {}", x),
+            Instruction::SetIToBcdOfReg(x) => write!(f, "LD B, {}", x),
+            Instruction::SetPitchToReg(x) => write!(f, "PITCH {}", x),
+            Instruction::RegDump(x) => write!(f, "LD [I], {}", x),
+            Instruction::RegLoad(x) => write!(f, "LD {}, [I]", x),
+            Instruction::SaveFlags(x) => write!(f, "LD R, {}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD {}, R", x),
+        }
+    }
+}
+
+impl From<Instruction> for u16 {
+    fn from(instruction: Instruction) -> Self {
+        instruction.to_u16()
+    }
+}
+
+impl TryFrom<u16> for Instruction {
+    type Error = DecodeError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Instruction::from_u16(value)
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use proptest::prelude::*;
+    use test_case::test_case;
+
+    /// Any instruction, generated from the opcodes that decode.
+    fn any_instruction() -> impl Strategy<Value = Instruction> {
+        prop_oneof![
+            any::<u16>().prop_filter_map("unknown opcode", |v| Instruction::from_u16(v).ok()),
+            any::<u16>().prop_map(|nnnn| Instruction::SetILong(Addr(nnnn))),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn encoding_an_opcode_gives_it_back(value: u16) {
+            if let Ok(instruction) = Instruction::from_u16(value) {
+                prop_assert_eq!(instruction.to_u16(), value);
+            }
+        }
+
+        #[test]
+        fn decoding_an_encoded_instruction_gives_it_back(instruction in any_instruction()) {
+            prop_assert_eq!(Instruction::decode(&instruction.to_bytes()), Ok(instruction));
+            prop_assert_eq!(instruction.to_bytes().len(), instruction.size() as usize);
+            if instruction.size() == 2 {
+                prop_assert_eq!(Instruction::from_u16(instruction.to_u16()), Ok(instruction));
+            }
+        }
+    }
+
+    #[test]
+    fn bitshifts_keep_vy() {
+        let instruction = Instruction::BitshiftRight(Reg(1), Reg(2));
+        assert_eq!(Ok(instruction), Instruction::from_u16(0x8126));
+        assert_eq!(instruction.to_u16(), 0x8126);
+        assert_eq!(Instruction::BitshiftLeft(Reg(3), Reg(4)).to_u16(), 0x834E);
+    }
+
+    #[test_case(0x00E0 => "CLS"; "clear screen")]
+    #[test_case(0x1234 => "JP 0x234"; "goto")]
+    #[test_case(0x6012 => "LD V0, 0x12"; "set reg to const")]
+    #[test_case(0x7AFF => "ADD VA, 0xFF"; "inc reg by const")]
+    #[test_case(0x8AB6 => "SHR VA, VB"; "bitshift right")]
+    #[test_case(0xB00A => "JP V0, 0x00A"; "jump with offset")]
+    #[test_case(0xDAB5 => "DRW VA, VB, 5"; "draw")]
+    #[test_case(0xF155 => "LD [I], V1"; "reg dump")]
+    #[test_case(0xF265 => "LD V2, [I]"; "reg load")]
+    #[test_case(0x00C4 => "SCD 4"; "scroll down")]
+    #[test_case(0xF201 => "PLANE 2"; "select planes")]
+    fn instructions_are_displayed_as_mnemonics(opcode: u16) -> String {
+        Instruction::from_u16(opcode).unwrap().to_string()
+    }
+
+    #[test]
+    fn long_set_i_is_displayed_with_full_address() {
+        assert_eq!(
+            Instruction::SetILong(Addr(0xABCD)).to_string(),
+            "LD I, long 0xABCD"
+        );
+    }
+
+    #[test]
+    fn long_set_i_is_encoded_as_four_bytes() {
+        let instruction = Instruction::SetILong(Addr(0x1234));
+        assert_eq!(instruction.to_bytes(), [0xF0, 0x00, 0x12, 0x34]);
+    }
 
     #[test]
     fn opcodes_are_parsed_correctly() {
-        assert_eq!(Instruction::ClearScreen, Instruction::from_u16(0x00E0));
-        assert_eq!(Instruction::Return, Instruction::from_u16(0x00EE));
-        assert_eq!(Instruction::Goto(Addr(0x25)), Instruction::from_u16(0x1025));
-        assert_eq!(Instruction::Call(Addr(0x37)), Instruction::from_u16(0x2037));
+        assert_eq!(Ok(Instruction::ClearScreen), Instruction::from_u16(0x00E0));
+        assert_eq!(Ok(Instruction::Return), Instruction::from_u16(0x00EE));
+        assert_eq!(
+            Ok(Instruction::Goto(Addr(0x25))),
+            Instruction::from_u16(0x1025)
+        );
         assert_eq!(
-            Instruction::IfRegEqConst(Reg(0xA), Const(8)),
+            Ok(Instruction::Call(Addr(0x37))),
+            Instruction::from_u16(0x2037)
+        );
+        assert_eq!(
+            Ok(Instruction::IfRegEqConst(Reg(0xA), Const(8))),
             Instruction::from_u16(0x3A08)
         );
         assert_eq!(
-            Instruction::IfRegNeqConst(Reg(0xA), Const(8)),
+            Ok(Instruction::IfRegNeqConst(Reg(0xA), Const(8))),
             Instruction::from_u16(0x4A08)
         );
         assert_eq!(
-            Instruction::IfRegNeqConst(Reg(0xA), Const(8)),
+            Ok(Instruction::IfRegNeqConst(Reg(0xA), Const(8))),
             Instruction::from_u16(0x4A08)
         );
         assert_eq!(
-            Instruction::SetRegToConst(Reg(0xB), Const(0x23)),
+            Ok(Instruction::SetRegToConst(Reg(0xB), Const(0x23))),
             Instruction::from_u16(0x6B23)
         );
         assert_eq!(
-            Instruction::IncRegByConst(Reg(0xC), Const(0xA1)),
+            Ok(Instruction::IncRegByConst(Reg(0xC), Const(0xA1))),
             Instruction::from_u16(0x7CA1)
         );
         assert_eq!(
-            Instruction::SetRegToReg(Reg(0xA), Reg(0xB)),
+            Ok(Instruction::SetRegToReg(Reg(0xA), Reg(0xB))),
             Instruction::from_u16(0x8AB0)
         );
         assert_eq!(
-            Instruction::BitwiseOr(Reg(0xD), Reg(0xE)),
+            Ok(Instruction::BitwiseOr(Reg(0xD), Reg(0xE))),
             Instruction::from_u16(0x8DE1)
         );
         assert_eq!(
-            Instruction::BitwiseAnd(Reg(0xD), Reg(0xE)),
+            Ok(Instruction::BitwiseAnd(Reg(0xD), Reg(0xE))),
             Instruction::from_u16(0x8DE2)
         );
         assert_eq!(
-            Instruction::BitwiseXor(Reg(0xD), Reg(0xE)),
+            Ok(Instruction::BitwiseXor(Reg(0xD), Reg(0xE))),
             Instruction::from_u16(0x8DE3)
         );
         assert_eq!(
-            Instruction::IncRegByReg(Reg(0xA), Reg(0xB)),
+            Ok(Instruction::IncRegByReg(Reg(0xA), Reg(0xB))),
             Instruction::from_u16(0x8AB4)
         );
         assert_eq!(
-            Instruction::DecRegByReg(Reg(0xA), Reg(0xB)),
+            Ok(Instruction::DecRegByReg(Reg(0xA), Reg(0xB))),
             Instruction::from_u16(0x8AB5)
         );
         assert_eq!(
-            Instruction::BitshiftRight(Reg(0xA)),
+            Ok(Instruction::BitshiftRight(Reg(0xA), Reg(0xB))),
             Instruction::from_u16(0x8AB6)
         );
         assert_eq!(
-            Instruction::SetVxVyMinusVx(Reg(0xA), Reg(0xB)),
+            Ok(Instruction::SetVxVyMinusVx(Reg(0xA), Reg(0xB))),
             Instruction::from_u16(0x8AB7)
         );
         assert_eq!(
-            Instruction::BitshiftLeft(Reg(0xA)),
+            Ok(Instruction::BitshiftLeft(Reg(0xA), Reg(0))),
             Instruction::from_u16(0x8A0E)
         );
         assert_eq!(
-            Instruction::IfRegNeqReg(Reg(0xA), Reg(0xB)),
+            Ok(Instruction::IfRegNeqReg(Reg(0xA), Reg(0xB))),
             Instruction::from_u16(0x9AB0)
         );
-        assert_eq!(Instruction::SetI(Addr(0x25)), Instruction::from_u16(0xA025));
         assert_eq!(
-            Instruction::SetPcToV0PlusAddr(Addr(0x25)),
+            Ok(Instruction::SetI(Addr(0x25))),
+            Instruction::from_u16(0xA025)
+        );
+        assert_eq!(
+            Ok(Instruction::SetPcToV0PlusAddr(Addr(0x25))),
             Instruction::from_u16(0xB025)
         );
         assert_eq!(
-            Instruction::SetVxRand(Reg(0xA), Const(0x23)),
+            Ok(Instruction::SetVxRand(Reg(0xA), Const(0x23))),
             Instruction::from_u16(0xCA23)
         );
         assert_eq!(
-            Instruction::Draw(Reg(0xA), Reg(0xB), Const(0xC)),
+            Ok(Instruction::Draw(Reg(0xA), Reg(0xB), Const(0xC))),
             Instruction::from_u16(0xDABC)
         );
         assert_eq!(
-            Instruction::IfKeyEqVx(Reg(0xA)),
+            Ok(Instruction::IfKeyEqVx(Reg(0xA))),
             Instruction::from_u16(0xEA9E)
         );
         assert_eq!(
-            Instruction::IfKeyNeqVx(Reg(0xA)),
+            Ok(Instruction::IfKeyNeqVx(Reg(0xA))),
             Instruction::from_u16(0xEAA1)
         );
         assert_eq!(
-            Instruction::SetRegToDelayTimer(Reg(0xA)),
+            Ok(Instruction::SetRegToDelayTimer(Reg(0xA))),
             Instruction::from_u16(0xFA07)
         );
         assert_eq!(
-            Instruction::SetRegToGetKey(Reg(0xA)),
+            Ok(Instruction::SetRegToGetKey(Reg(0xA))),
             Instruction::from_u16(0xFA0A)
         );
         assert_eq!(
-            Instruction::SetDelayTimerToReg(Reg(0xA)),
+            Ok(Instruction::SetDelayTimerToReg(Reg(0xA))),
             Instruction::from_u16(0xFA15)
         );
         assert_eq!(
-            Instruction::SetSoundTimerToReg(Reg(0xA)),
+            Ok(Instruction::SetSoundTimerToReg(Reg(0xA))),
             Instruction::from_u16(0xFA18)
         );
         assert_eq!(
-            Instruction::AddRegToI(Reg(0xA)),
+            Ok(Instruction::AddRegToI(Reg(0xA))),
             Instruction::from_u16(0xFA1E)
         );
         assert_eq!(
-            Instruction::SetIToSpriteAddrVx(Reg(0xA)),
+            Ok(Instruction::SetIToSpriteAddrVx(Reg(0xA))),
             Instruction::from_u16(0xFA29)
         );
         assert_eq!(
-            Instruction::SetIToBcdOfReg(Reg(0xA)),
+            Ok(Instruction::SetIToBcdOfReg(Reg(0xA))),
             Instruction::from_u16(0xFA33)
         );
         assert_eq!(
-            Instruction::RegDump(Reg(0xA)),
+            Ok(Instruction::RegDump(Reg(0xA))),
             Instruction::from_u16(0xFA55)
         );
         assert_eq!(
-            Instruction::RegLoad(Reg(0xA)),
+            Ok(Instruction::RegLoad(Reg(0xA))),
             Instruction::from_u16(0xFA65)
         );
     }
 
+    #[test]
+    fn super_chip_opcodes_are_parsed_correctly() {
+        assert_eq!(
+            Ok(Instruction::ScrollDown(Const(0xA))),
+            Instruction::from_u16(0x00CA)
+        );
+        assert_eq!(Ok(Instruction::ScrollRight), Instruction::from_u16(0x00FB));
+        assert_eq!(Ok(Instruction::ScrollLeft), Instruction::from_u16(0x00FC));
+        assert_eq!(Ok(Instruction::Exit), Instruction::from_u16(0x00FD));
+        assert_eq!(Ok(Instruction::LowRes), Instruction::from_u16(0x00FE));
+        assert_eq!(Ok(Instruction::HighRes), Instruction::from_u16(0x00FF));
+        assert_eq!(
+            Ok(Instruction::Draw(Reg(0xA), Reg(0xB), Const(0))),
+            Instruction::from_u16(0xDAB0)
+        );
+        assert_eq!(
+            Ok(Instruction::SetIToBigSpriteAddrVx(Reg(0xA))),
+            Instruction::from_u16(0xFA30)
+        );
+        assert_eq!(
+            Ok(Instruction::SaveFlags(Reg(0xA))),
+            Instruction::from_u16(0xFA75)
+        );
+        assert_eq!(
+            Ok(Instruction::LoadFlags(Reg(0xA))),
+            Instruction::from_u16(0xFA85)
+        );
+    }
+
+    #[test]
+    fn xo_chip_opcodes_are_parsed_correctly() {
+        assert_eq!(
+            Ok(Instruction::ScrollUp(Const(0xA))),
+            Instruction::from_u16(0x00DA)
+        );
+        assert_eq!(
+            Ok(Instruction::SaveRegRange(Reg(0xA), Reg(0xB))),
+            Instruction::from_u16(0x5AB2)
+        );
+        assert_eq!(
+            Ok(Instruction::LoadRegRange(Reg(0xA), Reg(0xB))),
+            Instruction::from_u16(0x5AB3)
+        );
+        assert_eq!(
+            Ok(Instruction::SelectPlanes(Const(3))),
+            Instruction::from_u16(0xF301)
+        );
+        assert_eq!(
+            Ok(Instruction::LoadAudioPattern),
+            Instruction::from_u16(0xF002)
+        );
+        assert_eq!(
+            Ok(Instruction::SetPitchToReg(Reg(0xA))),
+            Instruction::from_u16(0xFA3A)
+        );
+    }
+
+    #[test]
+    fn long_set_i_is_decoded_from_four_bytes() {
+        assert_eq!(
+            Ok(Instruction::SetILong(Addr(0xBEEF))),
+            Instruction::decode(&[0xF0, 0x00, 0xBE, 0xEF])
+        );
+        assert_eq!(4, Instruction::SetILong(Addr(0xBEEF)).size());
+        assert!(Instruction::decode(&[0xF0, 0x00]).is_err());
+        assert!(Instruction::from_u16(0xF000).is_err());
+        assert_eq!(
+            Instruction::from_u16(0x00E0),
+            Instruction::decode(&[0x00, 0xE0, 0xF0, 0x00])
+        );
+    }
+
     #[test]
     fn from_two_u8_equals_from_u16() {
         assert_eq!(
@@ -297,6 +723,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unknown_opcodes_are_errors() {
+        assert_eq!(
+            Err(DecodeError {
+                opcode: 0x0000,
+                address: None
+            }),
+            Instruction::from_u16(0x0000)
+        );
+        assert_eq!(
+            Err(DecodeError {
+                opcode: 0xF0FF,
+                address: None
+            }),
+            Instruction::try_from(0xF0FF)
+        );
+        assert_eq!(
+            Some(0x204),
+            Instruction::from_u16(0x5AB1)
+                .map_err(|e| e.at(0x204))
+                .unwrap_err()
+                .address
+        );
+    }
+
     #[test]
     fn split_u16_test() {
         assert_eq!((0x12, 0x34), Instruction::split_u16(0x1234));
@@ -0,0 +1,18 @@
+/// A read or write of a byte of memory made by an instruction,
+/// such as `Draw` reading a sprite or `RegDump` writing registers.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MemoryAccess {
+    /// The byte `value` was read from `address`.
+    Read { address: u16, value: u8 },
+    /// The byte `value` was written to `address`.
+    Write { address: u16, value: u8 },
+}
+
+impl MemoryAccess {
+    /// The address that was accessed.
+    pub fn address(&self) -> u16 {
+        match *self {
+            MemoryAccess::Read { address, .. } | MemoryAccess::Write { address, .. } => address,
+        }
+    }
+}
@@ -1,5 +1,13 @@
+pub mod audio;
 pub mod emulator;
 pub use emulator::Emulator;
+pub mod fault;
 pub mod input;
 pub mod instruction;
+pub mod memory_access;
+pub mod movie;
 pub mod output;
+pub mod quirks;
+pub mod rewind;
+pub mod snapshot;
+pub mod trace;
@@ -0,0 +1,366 @@
+//! Recording the keys held in every frame, to replay them exactly later.
+//!
+//! A `Recorder` wraps the input of an emulator and records the keys it holds at the end of every frame,
+//! and a `Replay` presents them to another emulator again.
+//! Both change keys only between frames, so the keys the emulator sees are exactly the ones recorded.
+//! Together with the program, the seed of the random number generator,
+//! the quirks and the number of instructions per frame stored in a `Movie`,
+//! this makes a replay run exactly like the recording did.
+
+use crate::emulator::input::{EmulatorInput, KeyEvent};
+use crate::emulator::quirks::{LoadStoreIncrement, Quirks};
+use crate::util::crc32::crc32;
+use crate::util::reader::{ReadError, Reader};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// The bytes every movie file starts with.
+const MAGIC: &[u8; 4] = b"CH8M";
+/// The version of the movie file format, increased whenever the layout changes.
+const VERSION: u16 = 1;
+
+/// Everything needed to replay a recording.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Movie {
+    /// The checksum of the program, see `matches_program`.
+    pub rom_checksum: u32,
+    /// The seed passed to `Emulator::seed_rng`.
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub cycles_per_frame: u32,
+    /// The keys held in each frame, with bit `n` set while key `n` is.
+    pub frames: Vec<u16>,
+}
+
+/// An error from reading a movie.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MovieError {
+    /// The data is not a movie.
+    InvalidMagic,
+    /// The movie was written by a different version of the file format.
+    UnsupportedVersion(u16),
+    /// The data ends before the movie does.
+    Truncated,
+    /// The data has the right layout, but contains impossible values.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::InvalidMagic => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {}", version)
+            }
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Corrupt(reason) => write!(f, "movie is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<ReadError> for MovieError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => MovieError::Truncated,
+            ReadError::InvalidBoolean => MovieError::Corrupt("invalid boolean"),
+        }
+    }
+}
+
+impl Movie {
+    /// Start a movie of `program`, which has no frames yet.
+    pub fn new(program: &[u8], seed: u64, quirks: Quirks, cycles_per_frame: u32) -> Movie {
+        Movie {
+            rom_checksum: crc32(program),
+            seed,
+            quirks,
+            cycles_per_frame,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Whether the movie was recorded with `program`.
+    pub fn matches_program(&self, program: &[u8]) -> bool {
+        self.rom_checksum == crc32(program)
+    }
+
+    /// Serialize the movie, with all numbers stored in little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_checksum.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        let quirks = &self.quirks;
+        bytes.push(quirks.shift_uses_vy as u8);
+        bytes.push(match quirks.load_store_increment {
+            LoadStoreIncrement::Unchanged => 0,
+            LoadStoreIncrement::ByX => 1,
+            LoadStoreIncrement::ByXPlusOne => 2,
+        });
+        bytes.push(quirks.jump_uses_vx as u8);
+        bytes.push(quirks.logic_resets_vf as u8);
+        bytes.push(quirks.clip_sprites as u8);
+        bytes.push(quirks.display_wait as u8);
+        bytes.extend_from_slice(&(quirks.memory_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.cycles_per_frame.to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        bytes.reserve(2 * self.frames.len());
+        for keys in &self.frames {
+            bytes.extend_from_slice(&keys.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Deserialize a movie written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(MovieError::InvalidMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let rom_checksum = reader.u32()?;
+        let seed = reader.u64()?;
+        let quirks = Quirks {
+            shift_uses_vy: reader.bool()?,
+            load_store_increment: match reader.u8()? {
+                0 => LoadStoreIncrement::Unchanged,
+                1 => LoadStoreIncrement::ByX,
+                2 => LoadStoreIncrement::ByXPlusOne,
+                _ => return Err(MovieError::Corrupt("invalid load/store increment")),
+            },
+            jump_uses_vx: reader.bool()?,
+            logic_resets_vf: reader.bool()?,
+            clip_sprites: reader.bool()?,
+            display_wait: reader.bool()?,
+            memory_size: reader.u32()? as usize,
+        };
+        let cycles_per_frame = reader.u32()?;
+        let frame_count = reader.u32()? as usize;
+        let rest = reader.rest();
+        if rest.len() != 2 * frame_count {
+            return Err(if rest.len() < 2 * frame_count {
+                MovieError::Truncated
+            } else {
+                MovieError::Corrupt("trailing bytes")
+            });
+        }
+        let frames = rest
+            .chunks_exact(2)
+            .map(|keys| u16::from_le_bytes([keys[0], keys[1]]))
+            .collect();
+
+        if quirks.memory_size < 4096 || quirks.memory_size > 0x10000 {
+            return Err(MovieError::Corrupt("invalid memory size"));
+        }
+        Ok(Movie {
+            rom_checksum,
+            seed,
+            quirks,
+            cycles_per_frame,
+            frames,
+        })
+    }
+}
+
+/// Keys that only change between frames, with the events for each change.
+#[derive(Default)]
+struct FrameKeys {
+    pressed: u16,
+    events: VecDeque<KeyEvent>,
+}
+
+impl FrameKeys {
+    fn set(&mut self, pressed: u16) {
+        for key in 0..16 {
+            let bit = 1 << key;
+            if (self.pressed ^ pressed) & bit != 0 {
+                self.events.push_back(match pressed & bit {
+                    0 => KeyEvent::Released(key),
+                    _ => KeyEvent::Pressed(key),
+                });
+            }
+        }
+        self.pressed = pressed;
+    }
+}
+
+/// Input that records the keys another input holds at the end of every frame,
+/// and presents them to the emulator during the next one.
+/// Keys pressed and released within a single frame are missed.
+pub struct Recorder<I: EmulatorInput> {
+    input: I,
+    frames: Vec<u16>,
+    keys: FrameKeys,
+}
+
+impl<I: EmulatorInput> Recorder<I> {
+    pub fn new(input: I) -> Recorder<I> {
+        Recorder {
+            input,
+            frames: Vec::new(),
+            keys: FrameKeys::default(),
+        }
+    }
+
+    /// The keys held in each frame so far, for `Movie::frames`.
+    pub fn frames(&self) -> &[u16] {
+        &self.frames
+    }
+}
+
+impl<I: EmulatorInput> EmulatorInput for Recorder<I> {
+    fn pressed_keys(&self) -> u16 {
+        self.keys.pressed
+    }
+
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        self.keys.events.pop_front()
+    }
+
+    fn end_frame(&mut self) {
+        self.input.end_frame();
+        // The events are recreated from the keys held in each frame instead
+        while self.input.poll_event().is_some() {}
+        let pressed = self.input.pressed_keys();
+        self.frames.push(pressed);
+        self.keys.set(pressed);
+    }
+}
+
+/// Input that presents the keys recorded by a `Recorder`, releasing all of them after the last frame.
+pub struct Replay {
+    frames: Vec<u16>,
+    frame: usize,
+    keys: FrameKeys,
+}
+
+impl Replay {
+    pub fn new(frames: Vec<u16>) -> Replay {
+        Replay {
+            frames,
+            frame: 0,
+            keys: FrameKeys::default(),
+        }
+    }
+
+    /// Whether every recorded frame has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.frames.len()
+    }
+}
+
+impl EmulatorInput for Replay {
+    fn pressed_keys(&self) -> u16 {
+        self.keys.pressed
+    }
+
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        self.keys.events.pop_front()
+    }
+
+    fn end_frame(&mut self) {
+        let pressed = self.frames.get(self.frame).copied().unwrap_or(0);
+        self.frame += 1;
+        self.keys.set(pressed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::emulator::output::DummyOutput;
+    use crate::emulator::Emulator;
+
+    /// Adds random numbers to V3, counts the frames where key 0 isn't held in V2,
+    /// and waits for a key in between.
+    const PROGRAM: [u8; 12] = [
+        0xC0, 0xFF, // RND V0, 0xFF
+        0x83, 0x04, // ADD V3, V0
+        0xE1, 0x9E, // SKP V1
+        0x72, 0x01, // ADD V2, 1
+        0xF4, 0x0A, // LD V4, K
+        0x12, 0x00, // JP 0x200
+    ];
+
+    #[test]
+    fn replay_runs_like_the_recording() {
+        let keys = vec![0, 0b1, 0b1, 0, 0b100, 0b110, 0, 0, 0b1000, 0, 0b1, 0];
+        let quirks = Quirks::COSMAC_VIP;
+        let mut movie = Movie::new(&PROGRAM, 1234, quirks, 3);
+
+        let mut recording = Emulator::with_quirks(
+            Recorder::new(Replay::new(keys.clone())),
+            DummyOutput::new(),
+            quirks,
+        );
+        recording.load(&PROGRAM);
+        recording.seed_rng(movie.seed);
+        for _ in 0..keys.len() {
+            recording
+                .run_frame(movie.cycles_per_frame as usize)
+                .unwrap();
+        }
+        movie.frames = recording.input().frames().to_vec();
+        assert_eq!(movie.frames, keys);
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert!(movie.matches_program(&PROGRAM));
+        let mut replay = Emulator::with_quirks(
+            Replay::new(movie.frames.clone()),
+            DummyOutput::new(),
+            quirks,
+        );
+        replay.load(&PROGRAM);
+        replay.seed_rng(movie.seed);
+        while !replay.input().is_finished() {
+            replay.run_frame(movie.cycles_per_frame as usize).unwrap();
+        }
+        assert_eq!(replay.snapshot(), recording.snapshot());
+        assert_ne!(replay.registers()[2], 0);
+        assert_ne!(replay.registers()[3], 0);
+    }
+
+    #[test]
+    fn every_load_store_increment_is_serialized() {
+        for load_store_increment in [
+            LoadStoreIncrement::Unchanged,
+            LoadStoreIncrement::ByX,
+            LoadStoreIncrement::ByXPlusOne,
+        ] {
+            let quirks = Quirks {
+                load_store_increment,
+                ..Quirks::default()
+            };
+            let movie = Movie::new(&PROGRAM, 1, quirks, 10);
+            assert_eq!(Ok(movie.clone()), Movie::from_bytes(&movie.to_bytes()));
+        }
+    }
+
+    #[test]
+    fn serialized_movie_is_validated() {
+        let mut movie = Movie::new(&PROGRAM, 1, Quirks::default(), 10);
+        movie.frames = vec![1, 2, 3];
+        let bytes = movie.to_bytes();
+        assert_eq!(Ok(movie), Movie::from_bytes(&bytes));
+
+        assert_eq!(Err(MovieError::InvalidMagic), Movie::from_bytes(b"CH8S"));
+        assert_eq!(
+            Err(MovieError::Truncated),
+            Movie::from_bytes(&bytes[..bytes.len() - 1])
+        );
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(
+            Movie::from_bytes(&trailing),
+            Err(MovieError::Corrupt(_))
+        ));
+    }
+}
@@ -1,11 +1,21 @@
 use std::collections::HashMap;
 
 /// Represents an output device that can be written to.
+/// The screen starts out 64x32 pixels large.
+///
+/// The state of a pixel is a bitmask of the planes it is set in.
+/// Only XO-CHIP programs use the second plane,
+/// so the state is otherwise either 0 or 1.
 pub trait EmulatorOutput {
     fn set(&mut self, x: usize, y: usize, state: u8);
     fn get(&self, x: usize, y: usize) -> u8;
     fn clear(&mut self);
     fn refresh(&mut self);
+
+    /// Change the resolution of the screen, which also clears it.
+    fn resize(&mut self, _width: usize, _height: usize) {
+        self.clear();
+    }
 }
 
 /// A simple output device that keeps track of set coordinates.
@@ -0,0 +1,170 @@
+use std::str::FromStr;
+
+/// The behaviour of instructions that CHIP-8 interpreters disagree on,
+/// along with other differences between the platforms programs are written for.
+/// Programs are usually written for a specific interpreter,
+/// so pick the preset matching the platform the program targets.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Quirks {
+    /// `8XY6` and `8XYE` shift Vy and store the result in Vx,
+    /// instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// How far `FX55` and `FX65` move I after saving or loading registers.
+    pub load_store_increment: LoadStoreIncrement,
+    /// `BNNN` is interpreted as `BXNN`, jumping to XNN plus Vx instead of NNN plus V0.
+    pub jump_uses_vx: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around.
+    pub clip_sprites: bool,
+    /// Drawing a sprite ends the current frame, limiting programs to 60 sprites per second.
+    pub display_wait: bool,
+    /// The number of bytes of addressable memory, at least 4096.
+    /// XO-CHIP extends this to 64 KiB.
+    pub memory_size: usize,
+}
+
+/// How far `FX55` and `FX65` move I after saving or loading the registers V0 to Vx.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LoadStoreIncrement {
+    /// I is left as it is.
+    Unchanged,
+    /// I is increased by X, leaving it pointing at the last register, as on CHIP-48.
+    ByX,
+    /// I is increased by X + 1, leaving it pointing just past the last register, as on the COSMAC VIP.
+    ByXPlusOne,
+}
+
+impl LoadStoreIncrement {
+    /// The amount to add to I after saving or loading the registers V0 to Vx.
+    pub fn amount(self, x: u8) -> u16 {
+        match self {
+            LoadStoreIncrement::Unchanged => 0,
+            LoadStoreIncrement::ByX => x as u16,
+            LoadStoreIncrement::ByXPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+impl Quirks {
+    /// The original interpreter on the COSMAC VIP.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increment: LoadStoreIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: true,
+        memory_size: 4096,
+    };
+
+    /// CHIP-48 on the HP48 calculators.
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: LoadStoreIncrement::ByX,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        memory_size: 4096,
+    };
+
+    /// SUPER-CHIP as implemented by modern interpreters.
+    pub const SUPER_CHIP_MODERN: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: LoadStoreIncrement::Unchanged,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        memory_size: 4096,
+    };
+
+    /// SUPER-CHIP 1.1 as originally released for the HP48.
+    pub const SUPER_CHIP_LEGACY: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: LoadStoreIncrement::Unchanged,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: true,
+        memory_size: 4096,
+    };
+
+    /// XO-CHIP as specified by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increment: LoadStoreIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        memory_size: 0x10000,
+    };
+
+    /// All presets and their names.
+    pub const PRESETS: [(&'static str, Quirks); 5] = [
+        ("vip", Quirks::COSMAC_VIP),
+        ("chip48", Quirks::CHIP_48),
+        ("schip", Quirks::SUPER_CHIP_MODERN),
+        ("schip-legacy", Quirks::SUPER_CHIP_LEGACY),
+        ("xochip", Quirks::XO_CHIP),
+    ];
+}
+
+/// The behaviour expected by most programs written for modern interpreters.
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increment: LoadStoreIncrement::Unchanged,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            memory_size: 4096,
+        }
+    }
+}
+
+/// Look up a preset by name, such as `vip` or `schip`.
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Quirks::PRESETS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, quirks)| *quirks)
+            .ok_or_else(|| {
+                let names: Vec<_> = Quirks::PRESETS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown quirks preset {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn presets_can_be_parsed_by_name() {
+        assert_eq!(Ok(Quirks::COSMAC_VIP), "vip".parse());
+        assert_eq!(Ok(Quirks::SUPER_CHIP_LEGACY), "SCHIP-legacy".parse());
+        assert!("chip-9".parse::<Quirks>().is_err());
+    }
+
+    #[test]
+    fn presets_are_distinct() {
+        for (i, (name, quirks)) in Quirks::PRESETS.iter().enumerate() {
+            for (other_name, other) in &Quirks::PRESETS[i + 1..] {
+                assert_ne!(quirks, other, "{} and {} are the same", name, other_name);
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::emulator::snapshot::Snapshot;
+use std::collections::VecDeque;
+
+/// A history of snapshots that can be stepped back through, one at a time.
+///
+/// Only the most recent snapshot is stored in full.
+/// Every older snapshot is stored as the difference from the one after it,
+/// which is small since most of memory is unchanged from frame to frame.
+/// The oldest snapshots are dropped to stay within the memory budget.
+pub struct Rewind {
+    budget: usize,
+    latest: Option<Vec<u8>>,
+    /// Deltas that each turn a snapshot into the one before it, oldest first
+    deltas: VecDeque<Delta>,
+    deltas_size: usize,
+}
+
+/// How to get from a serialized snapshot to the one before it.
+enum Delta {
+    /// The XOR of the two snapshots, as alternating runs of zeroes and literal bytes.
+    Xor(Vec<u8>),
+    /// The full previous snapshot, used when the two differ in size.
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    fn size(&self) -> usize {
+        match self {
+            Delta::Xor(bytes) | Delta::Full(bytes) => bytes.len(),
+        }
+    }
+}
+
+impl Rewind {
+    /// Create an empty history that uses at most about `budget` bytes.
+    pub fn new(budget: usize) -> Rewind {
+        Rewind {
+            budget,
+            latest: None,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        }
+    }
+
+    /// The number of snapshots that can be stepped back through.
+    pub fn len(&self) -> usize {
+        match self.latest {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// The number of bytes used to store the history.
+    pub fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas_size
+    }
+
+    /// Forget all snapshots.
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+
+    /// Add a snapshot to the history, typically once per frame.
+    pub fn push(&mut self, snapshot: &Snapshot) {
+        let bytes = snapshot.to_bytes();
+        if let Some(previous) = self.latest.take() {
+            let delta = if previous.len() == bytes.len() {
+                Delta::Xor(encode_xor(&bytes, &previous))
+            } else {
+                Delta::Full(previous)
+            };
+            self.deltas_size += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(bytes);
+
+        // Drop the oldest snapshots, but always keep the latest one
+        while self.memory_usage() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.deltas_size -= delta.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// Take the most recent snapshot out of the history.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let latest = self.latest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.deltas_size -= delta.size();
+            self.latest = Some(match delta {
+                Delta::Xor(xor) => apply_xor(&latest, &xor),
+                Delta::Full(previous) => previous,
+            });
+        }
+        let snapshot = Snapshot::from_bytes(&latest).expect("rewind history holds valid snapshots");
+        Some(snapshot)
+    }
+}
+
+/// Encode the XOR of two slices of the same length,
+/// as pairs of a number of unchanged bytes followed by a number of changed bytes and their XOR.
+fn encode_xor(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+    while i < from.len() {
+        let unchanged = from[i..].iter().zip(&to[i..]).take_while(|(a, b)| a == b);
+        let unchanged = unchanged.count();
+        i += unchanged;
+
+        let changed = from[i..].iter().zip(&to[i..]).take_while(|(a, b)| a != b);
+        let changed: Vec<u8> = changed.map(|(a, b)| a ^ b).collect();
+        i += changed.len();
+
+        write_varint(&mut encoded, unchanged);
+        write_varint(&mut encoded, changed.len());
+        encoded.extend_from_slice(&changed);
+    }
+    encoded
+}
+
+/// Apply an XOR encoded by `encode_xor(from, to)` to `from`, giving `to`.
+fn apply_xor(from: &[u8], xor: &[u8]) -> Vec<u8> {
+    let mut to = from.to_vec();
+    let mut i = 0;
+    let mut pos = 0;
+    while pos < xor.len() {
+        i += read_varint(xor, &mut pos);
+        let changed = read_varint(xor, &mut pos);
+        for (byte, x) in to[i..i + changed].iter_mut().zip(&xor[pos..pos + changed]) {
+            *byte ^= x;
+        }
+        i += changed;
+        pos += changed;
+    }
+    to
+}
+
+/// Write a number seven bits at a time, least significant first,
+/// with the high bit set on every byte but the last.
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::emulator::Emulator;
+
+    /// Snapshots of a program that counts V0 up and draws it, one per step.
+    fn snapshots(count: usize) -> Vec<Snapshot> {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x00, 0xE0, 0xF0, 0x29, 0xD1, 0x15, 0x70, 0x01, 0x12, 0x00]);
+        (0..count)
+            .map(|_| {
+                emulator.step().unwrap();
+                emulator.snapshot()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pop_returns_snapshots_newest_first() {
+        let snapshots = snapshots(20);
+        let mut rewind = Rewind::new(usize::MAX);
+        for snapshot in &snapshots {
+            rewind.push(snapshot);
+        }
+        assert_eq!(rewind.len(), 20);
+        for snapshot in snapshots.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(snapshot));
+        }
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.pop(), None);
+        assert_eq!(rewind.memory_usage(), 0);
+    }
+
+    #[test]
+    fn deltas_are_small() {
+        let snapshots = snapshots(100);
+        let full_size = snapshots[0].to_bytes().len();
+        let mut rewind = Rewind::new(usize::MAX);
+        for snapshot in &snapshots {
+            rewind.push(snapshot);
+        }
+        assert!(rewind.memory_usage() < 2 * full_size);
+    }
+
+    #[test]
+    fn oldest_snapshots_are_dropped_to_fit_budget() {
+        let snapshots = snapshots(100);
+        let full_size = snapshots[0].to_bytes().len();
+        let mut rewind = Rewind::new(full_size + 100);
+        for snapshot in &snapshots {
+            rewind.push(snapshot);
+            assert!(rewind.memory_usage() <= full_size + 100);
+        }
+        let len = rewind.len();
+        assert!(1 < len && len < 100);
+        for snapshot in snapshots[100 - len..].iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(snapshot));
+        }
+    }
+
+    #[test]
+    fn xor_round_trips() {
+        let from = [0, 1, 2, 3, 4, 5, 6, 7];
+        let to = [0, 1, 9, 9, 4, 5, 6, 0];
+        assert_eq!(apply_xor(&from, &encode_xor(&from, &to)), to);
+    }
+}
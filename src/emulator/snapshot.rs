@@ -0,0 +1,252 @@
+use crate::emulator::emulator::{AUDIO_PATTERN_SIZE, NUM_FLAGS, NUM_REGISTERS, STACK_SIZE};
+use crate::util::reader::{ReadError, Reader};
+use std::fmt;
+
+/// The bytes every snapshot file starts with.
+const MAGIC: &[u8; 4] = b"CH8S";
+/// The version of the snapshot file format, increased whenever the layout changes.
+const VERSION: u16 = 2;
+
+/// A copy of the full state of an emulator, including the screen,
+/// which can be restored later or saved to a file.
+///
+/// Snapshots can only be restored into an emulator that has loaded the same program,
+/// which is verified using a checksum of the program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Snapshot {
+    pub(crate) rom_checksum: u32,
+    pub(crate) memory: Vec<u8>,
+    pub(crate) registers: [u8; NUM_REGISTERS],
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) i: u16,
+    pub(crate) program_counter: u16,
+    pub(crate) stack_pointer: u8,
+    pub(crate) stack: [u16; STACK_SIZE],
+    pub(crate) flags: [u8; NUM_FLAGS],
+    pub(crate) high_res: bool,
+    pub(crate) exited: bool,
+    pub(crate) waiting_for_vblank: bool,
+    pub(crate) planes: u8,
+    pub(crate) drawn_planes: u8,
+    pub(crate) audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub(crate) pitch: u8,
+    pub(crate) audio_pattern_loaded: bool,
+    /// The state of every pixel at the current resolution, row by row.
+    pub(crate) framebuffer: Vec<u8>,
+}
+
+/// An error from reading or restoring a snapshot.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SnapshotError {
+    /// The data is not a snapshot.
+    InvalidMagic,
+    /// The snapshot was written by a different version of the file format.
+    UnsupportedVersion(u16),
+    /// The data ends before the snapshot does.
+    Truncated,
+    /// The data has the right layout, but contains an impossible state.
+    Corrupt(&'static str),
+    /// The snapshot was taken with a different program loaded.
+    RomMismatch { expected: u32, found: u32 },
+    /// The snapshot was taken with a different amount of memory.
+    MemorySizeMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidMagic => write!(f, "not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Corrupt(reason) => write!(f, "snapshot is corrupt: {}", reason),
+            SnapshotError::RomMismatch { expected, found } => write!(
+                f,
+                "snapshot is of program with checksum {:08x}, but {:08x} is loaded",
+                found, expected
+            ),
+            SnapshotError::MemorySizeMismatch { expected, found } => write!(
+                f,
+                "snapshot has {} bytes of memory, but the emulator has {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<ReadError> for SnapshotError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => SnapshotError::Truncated,
+            ReadError::InvalidBoolean => SnapshotError::Corrupt("invalid boolean"),
+        }
+    }
+}
+
+impl Snapshot {
+    /// The CRC-32 checksum of the program that was loaded when the snapshot was taken.
+    pub fn rom_checksum(&self) -> u32 {
+        self.rom_checksum
+    }
+
+    /// The width and height of the screen in the snapshot.
+    fn screen_size(&self) -> (usize, usize) {
+        if self.high_res {
+            (128, 64)
+        } else {
+            (64, 32)
+        }
+    }
+
+    /// Serialize the snapshot, with all numbers stored in little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.memory.len() + self.framebuffer.len() + 1024);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_checksum.to_le_bytes());
+        bytes.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&self.registers);
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.extend_from_slice(&self.i.to_le_bytes());
+        bytes.extend_from_slice(&self.program_counter.to_le_bytes());
+        bytes.push(self.stack_pointer);
+        for address in self.stack.iter() {
+            bytes.extend_from_slice(&address.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.flags);
+        bytes.push(self.high_res as u8);
+        bytes.push(self.exited as u8);
+        bytes.push(self.waiting_for_vblank as u8);
+        bytes.push(self.planes);
+        bytes.push(self.drawn_planes);
+        bytes.extend_from_slice(&self.audio_pattern);
+        bytes.push(self.pitch);
+        bytes.push(self.audio_pattern_loaded as u8);
+        bytes.extend_from_slice(&self.framebuffer);
+        bytes
+    }
+
+    /// Deserialize a snapshot written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let rom_checksum = reader.u32()?;
+        let memory_size = reader.u32()? as usize;
+        let memory = reader.take(memory_size)?.to_vec();
+        let registers = reader.array()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let i = reader.u16()?;
+        let program_counter = reader.u16()?;
+        let stack_pointer = reader.u8()?;
+        let mut stack = [0; STACK_SIZE];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let flags = reader.array()?;
+        let high_res = reader.bool()?;
+        let exited = reader.bool()?;
+        let waiting_for_vblank = reader.bool()?;
+        let planes = reader.u8()?;
+        let drawn_planes = reader.u8()?;
+        let audio_pattern = reader.array()?;
+        let pitch = reader.u8()?;
+        let audio_pattern_loaded = reader.bool()?;
+
+        let mut snapshot = Snapshot {
+            rom_checksum,
+            memory,
+            registers,
+            delay_timer,
+            sound_timer,
+            i,
+            program_counter,
+            stack_pointer,
+            stack,
+            flags,
+            high_res,
+            exited,
+            waiting_for_vblank,
+            planes,
+            drawn_planes,
+            audio_pattern,
+            pitch,
+            audio_pattern_loaded,
+            framebuffer: Vec::new(),
+        };
+        let (width, height) = snapshot.screen_size();
+        snapshot.framebuffer = reader.take(width * height)?.to_vec();
+
+        if planes > 0b11 || drawn_planes > 0b11 {
+            return Err(SnapshotError::Corrupt("invalid plane selection"));
+        }
+        if !reader.rest().is_empty() {
+            return Err(SnapshotError::Corrupt("trailing bytes"));
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::emulator::Emulator;
+
+    fn snapshot() -> Snapshot {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[0x00, 0xFF, 0xD0, 0x15]);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        emulator.snapshot()
+    }
+
+    #[test]
+    fn snapshot_survives_serialization() {
+        let snapshot = snapshot();
+        assert_eq!(
+            Ok(snapshot.clone()),
+            Snapshot::from_bytes(&snapshot.to_bytes())
+        );
+    }
+
+    #[test]
+    fn serialized_snapshot_is_validated() {
+        let bytes = snapshot().to_bytes();
+        assert_eq!(
+            Err(SnapshotError::InvalidMagic),
+            Snapshot::from_bytes(b"CH8X")
+        );
+        assert_eq!(
+            Err(SnapshotError::Truncated),
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1])
+        );
+
+        let mut future = bytes.clone();
+        future[4] = VERSION as u8 + 1;
+        assert_eq!(
+            Err(SnapshotError::UnsupportedVersion(VERSION + 1)),
+            Snapshot::from_bytes(&future)
+        );
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(
+            Snapshot::from_bytes(&trailing),
+            Err(SnapshotError::Corrupt(_))
+        ));
+    }
+}
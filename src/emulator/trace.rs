@@ -0,0 +1,82 @@
+use crate::emulator::instruction::Instruction;
+use std::io::{self, Write};
+
+/// The width of the mnemonic column, which fits the longest mnemonic.
+const MNEMONIC_WIDTH: usize = 20;
+
+/// Writes a line for every instruction the emulator executes, for comparing with other emulators.
+///
+/// Each line has the number of the instruction counting from 1, its address, its opcode and mnemonic,
+/// followed by the registers, I, the stack pointer and the timers after executing it,
+/// all in fixed width columns of hexadecimal:
+///
+/// ```text
+/// 000000001 0200 6012     LD V0, 0x12          V=12000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00
+/// ```
+///
+/// Instructions that fault are not written.
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    cycle: u64,
+}
+
+/// The state written after each instruction.
+pub(crate) struct TraceState<'a> {
+    pub registers: &'a [u8],
+    pub i: u16,
+    pub stack_pointer: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Tracer {
+    /// Trace to a writer, which should be buffered since it is written to for every instruction.
+    pub fn new(writer: impl Write + Send + 'static) -> Tracer {
+        Tracer {
+            writer: Box::new(writer),
+            cycle: 0,
+        }
+    }
+
+    /// The number of instructions traced so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn trace(
+        &mut self,
+        pc: u16,
+        instruction: Instruction,
+        state: TraceState,
+    ) -> io::Result<()> {
+        self.cycle += 1;
+        let opcode: String = instruction
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let registers: String = state
+            .registers
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        writeln!(
+            self.writer,
+            "{:09} {:04X} {:<8} {:<width$} V={} I={:04X} SP={:02X} DT={:02X} ST={:02X}",
+            self.cycle,
+            pc,
+            opcode,
+            instruction.to_string(),
+            registers,
+            state.i,
+            state.stack_pointer,
+            state.delay_timer,
+            state.sound_timer,
+            width = MNEMONIC_WIDTH,
+        )
+    }
+}
@@ -0,0 +1,530 @@
+use crate::debugger::{Debugger, Pause, Watchpoint};
+use crate::emulator::audio::{DummyAudio, EmulatorAudio};
+use crate::emulator::fault::Fault;
+use crate::emulator::input::EmulatorInput;
+use crate::emulator::output::EmulatorOutput;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// The byte a debugger sends to interrupt a running program.
+const INTERRUPT: u8 = 0x03;
+
+/// The largest packet accepted, which is told to the debugger.
+const PACKET_SIZE: usize = 0x4000;
+
+/// The most bytes a single watchpoint packet can cover,
+/// since every watched byte is checked on every instruction.
+const MAX_WATCH_LEN: u16 = 16;
+
+/// The register numbers after V0 to VF, which are 0 to 15.
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+
+/// Signals reported to the debugger when the program stops.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// The registers as a GDB target description,
+/// in the order of the `g` packet, with I and PC in little endian.
+fn target_xml() -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\"?>",
+        "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+        "<target version=\"1.0\"><feature name=\"org.chip8.core\">"
+    ));
+    for x in 0..16 {
+        xml += &format!("<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", x);
+    }
+    xml += "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>";
+    xml += "<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>";
+    xml += "<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>";
+    xml += "</feature></target>";
+    xml
+}
+
+/// What to do after a packet.
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    /// Send a reply, which is empty for unsupported packets
+    Reply(String),
+    Step,
+    Continue,
+    /// Reply OK and close the connection
+    Detach,
+    /// Close the connection without replying
+    Kill,
+}
+
+/// A stub for the GDB remote serial protocol, so debuggers such as GDB can control the emulator.
+///
+/// The registers are V0 to VF, I, PC and SP, numbered 0 to 18.
+/// Software and hardware breakpoints are supported,
+/// along with read and write watchpoints, single-stepping and continuing.
+/// Continuing runs the program at 60 frames per second until it stops or is interrupted.
+pub struct GdbStub<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio = DummyAudio> {
+    debugger: Debugger<I, O, A>,
+}
+
+impl<I: EmulatorInput, O: EmulatorOutput, A: EmulatorAudio> GdbStub<I, O, A> {
+    pub fn new(debugger: Debugger<I, O, A>) -> Self {
+        GdbStub { debugger }
+    }
+
+    pub fn debugger(&self) -> &Debugger<I, O, A> {
+        &self.debugger
+    }
+
+    pub fn into_debugger(self) -> Debugger<I, O, A> {
+        self.debugger
+    }
+
+    /// Talk to a connected debugger until it detaches or disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut connection = Connection::new(stream);
+        while let Some(packet) = connection.read_packet()? {
+            log::debug!("<- {}", packet);
+            let reply = match self.command(&packet) {
+                Command::Reply(reply) => reply,
+                Command::Step => stop_reply(self.debugger.step()),
+                Command::Continue => self.resume(&mut connection)?,
+                Command::Detach => {
+                    connection.write_packet("OK")?;
+                    return Ok(());
+                }
+                Command::Kill => return Ok(()),
+            };
+            log::debug!("-> {}", reply);
+            connection.write_packet(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Run the program at full speed until it stops, or the debugger interrupts it.
+    fn resume(&mut self, connection: &mut Connection) -> io::Result<String> {
+        let frame_duration = Duration::from_secs(1) / 60;
+        let mut next_frame = Instant::now();
+        loop {
+            match self.debugger.run_frame() {
+                Ok(Pause::Done) => {}
+                result => return Ok(stop_reply(result)),
+            }
+            if connection.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+            next_frame += frame_duration;
+            std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    fn command(&mut self, packet: &str) -> Command {
+        let reply = |reply: Option<String>| Command::Reply(reply.unwrap_or_else(|| "E01".into()));
+        let (kind, args) = packet.split_at(std::cmp::min(1, packet.len()));
+        match kind {
+            "?" => Command::Reply(format!("S{:02x}", SIGTRAP)),
+            "g" => Command::Reply(encode_hex(&self.read_registers())),
+            "G" => reply(self.write_registers(args)),
+            "p" => reply(self.read_register(args)),
+            "P" => reply(self.write_register(args)),
+            "m" => reply(self.read_memory(args)),
+            "M" => reply(self.write_memory(args)),
+            "Z" => self.set_breakpoint(args, true),
+            "z" => self.set_breakpoint(args, false),
+            "s" if args.is_empty() => Command::Step,
+            "c" if args.is_empty() => Command::Continue,
+            "D" => Command::Detach,
+            "k" => Command::Kill,
+            // There is only one thread
+            "H" => Command::Reply("OK".into()),
+            "q" => Command::Reply(self.query(args)),
+            _ => Command::Reply(String::new()),
+        }
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE);
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            return match parse_pair(range, ',') {
+                Some((offset, len)) => {
+                    let xml = target_xml();
+                    let start = std::cmp::min(offset, xml.len());
+                    let end = std::cmp::min(start.saturating_add(len), xml.len());
+                    let more = if end < xml.len() { 'm' } else { 'l' };
+                    format!("{}{}", more, &xml[start..end])
+                }
+                None => "E01".into(),
+            };
+        }
+        match query {
+            "Attached" => "1".into(),
+            "C" => "QC1".into(),
+            _ => String::new(),
+        }
+    }
+
+    /// The registers in the order of the `g` packet.
+    fn read_registers(&self) -> Vec<u8> {
+        let emulator = self.debugger.emulator();
+        let mut bytes = emulator.registers().to_vec();
+        bytes.extend_from_slice(&emulator.i().to_le_bytes());
+        bytes.extend_from_slice(&emulator.program_counter().to_le_bytes());
+        bytes.push(emulator.stack().len() as u8);
+        bytes
+    }
+
+    fn write_registers(&mut self, hex: &str) -> Option<String> {
+        let bytes = decode_hex(hex)?;
+        if bytes.len() != REG_SP + 3 {
+            return None;
+        }
+        let emulator = self.debugger.emulator_mut();
+        emulator.registers_mut().copy_from_slice(&bytes[..REG_I]);
+        emulator.set_i(u16::from_le_bytes([bytes[16], bytes[17]]));
+        emulator.set_program_counter(u16::from_le_bytes([bytes[18], bytes[19]]));
+        emulator.set_stack_pointer(bytes[20]);
+        Some("OK".into())
+    }
+
+    fn read_register(&self, n: &str) -> Option<String> {
+        let emulator = self.debugger.emulator();
+        let bytes = match usize::from_str_radix(n, 16).ok()? {
+            x @ 0..=15 => vec![emulator.registers()[x]],
+            REG_I => emulator.i().to_le_bytes().to_vec(),
+            REG_PC => emulator.program_counter().to_le_bytes().to_vec(),
+            REG_SP => vec![emulator.stack().len() as u8],
+            _ => return None,
+        };
+        Some(encode_hex(&bytes))
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let (n, value) = args.split_once('=')?;
+        let bytes = decode_hex(value)?;
+        let emulator = self.debugger.emulator_mut();
+        match (usize::from_str_radix(n, 16).ok()?, bytes.as_slice()) {
+            (x @ 0..=15, &[value]) => emulator.registers_mut()[x] = value,
+            (REG_I, &[lo, hi]) => emulator.set_i(u16::from_le_bytes([lo, hi])),
+            (REG_PC, &[lo, hi]) => emulator.set_program_counter(u16::from_le_bytes([lo, hi])),
+            (REG_SP, &[sp]) => emulator.set_stack_pointer(sp),
+            _ => return None,
+        }
+        Some("OK".into())
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (address, len) = parse_pair(args, ',')?;
+        let memory = self.debugger.emulator().memory();
+        let end = std::cmp::min(address.checked_add(len)?, memory.len());
+        Some(encode_hex(memory.get(address..end)?))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, hex) = args.split_once(':')?;
+        let (address, len) = parse_pair(range, ',')?;
+        let bytes = decode_hex(hex)?;
+        let memory = self.debugger.emulator_mut().memory_mut();
+        if bytes.len() != len {
+            return None;
+        }
+        memory
+            .get_mut(address..address.checked_add(len)?)?
+            .copy_from_slice(&bytes);
+        Some("OK".into())
+    }
+
+    /// Handle `Z` and `z` packets, for kinds of breakpoints and watchpoints.
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> Command {
+        let mut parts = args.split(',');
+        let parsed = (|| {
+            let kind = parts.next()?;
+            let address = u16::from_str_radix(parts.next()?, 16).ok()?;
+            let len = u16::from_str_radix(parts.next()?, 16).ok()?;
+            Some((kind, address, len))
+        })();
+        let (kind, address, len) = match parsed {
+            Some(parsed) => parsed,
+            None => return Command::Reply("E01".into()),
+        };
+        if matches!(kind, "2" | "3" | "4") && len > MAX_WATCH_LEN {
+            return Command::Reply("E01".into());
+        }
+        let addresses = (0..len.max(1)).map(|offset| address.wrapping_add(offset));
+        let watchpoints: Vec<Watchpoint> = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return Command::Reply("OK".into());
+            }
+            "2" => addresses.map(Watchpoint::Write).collect(),
+            "3" => addresses.map(Watchpoint::Read).collect(),
+            "4" => addresses
+                .flat_map(|address| vec![Watchpoint::Write(address), Watchpoint::Read(address)])
+                .collect(),
+            _ => return Command::Reply(String::new()),
+        };
+        for watchpoint in watchpoints {
+            if insert {
+                self.debugger.add_watchpoint(watchpoint);
+            } else {
+                self.debugger.remove_watchpoint(watchpoint);
+            }
+        }
+        Command::Reply("OK".into())
+    }
+}
+
+/// The reply telling the debugger why the program stopped.
+fn stop_reply(result: Result<Pause, Fault>) -> String {
+    match result {
+        Ok(Pause::Done) | Ok(Pause::Breakpoint(_)) => format!("S{:02x}", SIGTRAP),
+        Ok(Pause::Exited) => "W00".into(),
+        Ok(Pause::Watchpoint { watchpoint, .. }) => match watchpoint {
+            Watchpoint::Write(address) => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+            Watchpoint::Read(address) => format!("T{:02x}rwatch:{:x};", SIGTRAP, address),
+            _ => format!("S{:02x}", SIGTRAP),
+        },
+        Err(fault) => {
+            log::warn!("{}", fault);
+            match fault {
+                Fault::InvalidOpcode(_) => format!("S{:02x}", SIGILL),
+                _ => format!("S{:02x}", SIGSEGV),
+            }
+        }
+    }
+}
+
+/// Parse two hexadecimal numbers separated by `separator`, such as an address and length.
+fn parse_pair(text: &str, separator: char) -> Option<(usize, usize)> {
+    let (a, b) = text.split_once(separator)?;
+    Some((
+        usize::from_str_radix(a, 16).ok()?,
+        usize::from_str_radix(b, 16).ok()?,
+    ))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The packet framing of the protocol, `$data#checksum`, acknowledged with `+` or `-`.
+struct Connection {
+    stream: TcpStream,
+    /// Bytes received but not yet read
+    buffer: VecDeque<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Connection {
+            stream,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Read a byte, or None if the debugger disconnected.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.buffer.is_empty() {
+            let mut bytes = [0; 256];
+            let len = self.stream.read(&mut bytes)?;
+            self.buffer.extend(&bytes[..len]);
+        }
+        Ok(self.buffer.pop_front())
+    }
+
+    /// Read the next packet, skipping acknowledgements and stray interrupts.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in &mut checksum {
+                match self.read_byte()? {
+                    Some(byte) => *digit = byte,
+                    None => return Ok(None),
+                }
+            }
+            let checksum = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if checksum != Some(sum(&data)) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, sum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    /// Whether the debugger has sent an interrupt, without waiting for one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut bytes = [0; 256];
+        let result = self.stream.read(&mut bytes);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(len) => {
+                self.buffer.extend(&bytes[..len]);
+                Ok(self.buffer.contains(&INTERRUPT))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The checksum of a packet, which is the sum of its bytes.
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::emulator::input::DummyInput;
+    use crate::emulator::output::DummyOutput;
+    use crate::emulator::Emulator;
+    use std::net::TcpListener;
+
+    fn stub() -> GdbStub<DummyInput, DummyOutput> {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&[
+            0x60, 0x12, // 200: LD V0, 0x12
+            0xA3, 0x45, // 202: LD I, 0x345
+            0x12, 0x04, // 204: JP 0x204
+        ]);
+        GdbStub::new(Debugger::new(emulator, 10))
+    }
+
+    fn reply(stub: &mut GdbStub<DummyInput, DummyOutput>, packet: &str) -> String {
+        match stub.command(packet) {
+            Command::Reply(reply) => reply,
+            other => panic!("expected a reply, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut stub = stub();
+        assert_eq!(stub.command("s"), Command::Step);
+        stub.debugger.step().unwrap();
+        stub.debugger.step().unwrap();
+        let registers = reply(&mut stub, "g");
+        assert_eq!(registers, format!("12{}4503040200", "00".repeat(15)));
+        assert_eq!(reply(&mut stub, "p11"), "0402");
+
+        assert_eq!(reply(&mut stub, "P1=ff"), "OK");
+        assert_eq!(reply(&mut stub, "P10=0003"), "OK");
+        assert_eq!(stub.debugger.emulator().registers()[1], 0xFF);
+        assert_eq!(stub.debugger.emulator().i(), 0x300);
+        assert_eq!(reply(&mut stub, "G"), "E01");
+
+        assert_eq!(reply(&mut stub, "m200,4"), "6012a345");
+        assert_eq!(reply(&mut stub, "M300,2:beef"), "OK");
+        assert_eq!(reply(&mut stub, "m300,2"), "beef");
+        assert_eq!(reply(&mut stub, "M300,2:be"), "E01");
+        assert_eq!(reply(&mut stub, "mffff,2"), "E01");
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut stub = stub();
+        assert_eq!(reply(&mut stub, "Z0,204,2"), "OK");
+        assert_eq!(stub.debugger.run(100), Ok(Pause::Breakpoint(0x204)));
+        assert_eq!(reply(&mut stub, "z0,204,2"), "OK");
+        assert_eq!(stub.debugger.breakpoints().count(), 0);
+
+        assert_eq!(reply(&mut stub, "Z2,300,2"), "OK");
+        assert_eq!(stub.debugger.watchpoints().count(), 2);
+        assert_eq!(reply(&mut stub, "z2,300,2"), "OK");
+        assert_eq!(stub.debugger.watchpoints().count(), 0);
+        assert_eq!(reply(&mut stub, "Z9,300,2"), "");
+        assert_eq!(reply(&mut stub, "Z4,0,ffff"), "E01");
+        assert_eq!(stub.debugger.watchpoints().count(), 0);
+    }
+
+    #[test]
+    fn target_description_is_sent_in_chunks() {
+        let mut stub = stub();
+        let xml = target_xml();
+        let first = reply(&mut stub, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(first, format!("m{}", &xml[..16]));
+        let rest = reply(
+            &mut stub,
+            &format!("qXfer:features:read:target.xml:10,{:x}", xml.len()),
+        );
+        assert_eq!(rest, format!("l{}", &xml[16..]));
+        let huge = reply(
+            &mut stub,
+            "qXfer:features:read:target.xml:1,ffffffffffffffff",
+        );
+        assert_eq!(huge, format!("l{}", &xml[1..]));
+    }
+
+    #[test]
+    fn session_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = stub();
+            stub.serve(stream).unwrap();
+            stub.into_debugger().into_emulator().program_counter()
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        let mut exchange = |packet: &str| {
+            let framed = format!("${}#{:02x}", packet, sum(packet.as_bytes()));
+            client.write_all(framed.as_bytes()).unwrap();
+            let mut received = Vec::new();
+            let mut byte = [0];
+            while !received.ends_with(b"#") {
+                client.read_exact(&mut byte).unwrap();
+                received.push(byte[0]);
+            }
+            let mut checksum = [0; 2];
+            client.read_exact(&mut checksum).unwrap();
+            String::from_utf8(received).unwrap()
+        };
+        assert_eq!(exchange("Z0,204,2"), "+$OK#");
+        assert_eq!(exchange("c"), "+$S05#");
+        assert_eq!(exchange("p11"), "+$0402#");
+        assert_eq!(exchange("D"), "+$OK#");
+        assert_eq!(server.join().unwrap(), 0x204);
+    }
+}
@@ -1,12 +1,109 @@
 /*!
 
 A CHIP-8 emulator as specified at https://en.wikipedia.org/wiki/CHIP-8.
+The SUPER-CHIP 1.1 extensions, including the 128x64 high resolution mode, are supported as well,
+along with the XO-CHIP extensions, which need `--quirks xochip` for 64 KiB of memory.
 
 # Crossterm Frontend
 
 If you want to try the emulator on some programs, there is a ready-to-use implementation
 you can run by using `cargo run --release --bin crossterm_frontend -- <program>`.
-You can then use the keys 0-9 and a-f to to give input, but which ones to use depend on the CHIP-8 program.
+The left side of the keyboard stands in for the hexadecimal keypad of the COSMAC VIP,
+but which keys to use depend on the CHIP-8 program. Press escape to quit.
+
+```text
+1 2 3 4      1 2 3 C
+q w e r  ->  4 5 6 D
+a s d f      7 8 9 E
+z x c v      A 0 B F
+```
+
+To use other keys, pass `--keymap <file>` with a TOML file that maps keys to CHIP-8 keys,
+where the keys are characters or one of space, enter, tab, up, down, left and right.
+Keys that are not in the file keep their default mapping.
+A `<program>.keys.toml` next to the program is applied on top, for keys specific to that program.
+
+Most terminals only tell when a key is pressed, repeating the press while it is held,
+so a key counts as held until it stops repeating.
+Terminals that support the [kitty keyboard protocol](https://sw.kovidgoyal.net/kitty/keyboard-protocol/)
+also tell when a key is released, which is used instead when available.
+
+```toml
+up = 0x5
+down = 0x8
+left = 0x7
+right = 0x9
+space = 0x6
+```
+
+Interpreters disagree on how a few instructions behave, so if a program misbehaves,
+try running it with the quirks of the platform it was written for, such as `--quirks vip` or `--quirks schip`.
+
+Press F1-F4 to save the state of the program to one of four slots, and F5-F8 to load it again.
+The slots are stored next to the program, as `<program>.state1` to `<program>.state4`.
+Hold backspace to rewind, up to as far back as `--rewind-budget` MiB of history allows.
+
+# Disassembler
+
+`cargo run --bin chip8-disasm -- <program>` prints the address, raw bytes and mnemonic of every instruction in a program,
+using the mnemonics from Cowgod's technical reference, such as `LD V0, 0x12` and `DRW VA, VB, 5`.
+Bytes that are not instructions are printed as `db` directives.
+With `--recursive`, only code that can be reached from the entry point is disassembled,
+and the output is assembly with generated labels such as `sub_2A4` and `sprite_300`,
+which assembles back into the same program.
+
+# Assembler
+
+`cargo run --bin chip8-asm -- <source> [-o <program>]` assembles a program from the same mnemonics.
+Lines can have `label:`s and `; comments`, and besides instructions there are `db` and `dw` directives for data,
+and constants such as `SPEED = 4`. Values can refer to labels defined later in the program.
+
+```text
+    LD I, digit
+    DRW V0, V0, 5
+loop:
+    JP loop
+digit:
+    db 0xF0, 0x90, 0x90, 0x90, 0xF0
+```
+
+The assembler can also be used as a library through `chip_8::assembler::assemble`.
+
+# Octo
+
+`cargo run --bin chip8-octo -- <source> [-o <program>]` compiles a program written in [Octo](https://github.com/JohnEarnest/Octo),
+with labels, `:const`, `:alias`, `:macro` and `:calc`, along with `if ... then`, `if ... begin ... else ... end`
+and `loop ... while ... again`.
+
+```text
+: main
+    v0 := 0
+    loop
+        i := hex v0
+        sprite v0 v0 5
+        v0 += 1
+        while v0 != 8
+    again
+```
+
+Like in Octo, `:calc` expressions are evaluated right to left, so use parentheses to group them.
+The compiler can also be used as a library through `chip_8::octo::compile`.
+
+# Debugger
+
+`cargo run --bin chip8-debug -- <program>` runs a program under a debugger on the command line.
+It shows the registers, I, the program counter, the stack, the timers and the code around the program counter,
+and can `step` through instructions, step over calls with `next`, set breakpoints with `break <address>`,
+and `continue` until one is reached. Type `help` for the full list of commands.
+Watchpoints pause right after an instruction reads or writes an address, changes a register to a value,
+or points I into a range, such as `watch w 3A0`, `watch v3 10` or `watch i 300 340`.
+
+Other frontends can do the same by wrapping their emulator in a `chip_8::debugger::Debugger`.
+The memory read and written by the last instruction is available from `emulator.memory_accesses()`.
+
+`cargo run --bin chip8-gdb -- <program> [--port 1234]` waits for a debugger that speaks the GDB remote serial protocol,
+such as GDB with `target remote localhost:1234`. The registers are V0 to VF, I, PC and SP, in that order,
+and memory can be read and written, with breakpoints, watchpoints, stepping and continuing.
 
 # Library
 
@@ -23,9 +120,12 @@ let mut emulator = Emulator::dummy();
 // Load a program at address 0x200.
 let clear_display = [0x00, 0xE0];
 emulator.load(&clear_display);
-emulator.step(); // Will now clear the display
+emulator.step().unwrap(); // Will now clear the display
 ```
 
+To run a program at the right speed, call `emulator.run_frame(cycles_per_frame)` 60 times per second.
+It executes the given number of instructions, then counts the delay and sound timers down.
+
 Alternatively, you can experiment by executing instructions manually.
 
 ```rust
@@ -35,21 +135,27 @@ use chip_8::emulator::instruction::{Instruction, Reg, Const, Addr};
 let mut emulator = Emulator::dummy();
 
 // Execute instructions manually
-emulator.execute_single(Instruction::ClearScreen);
+emulator.execute_single(Instruction::ClearScreen).unwrap();
 
 // Or many sequentially
 emulator.execute_many(&[
     Instruction::Goto(Addr(0x250)),
     Instruction::SetRegToConst(Reg(0xA), Const(35)),
     Instruction::SetRegToReg(Reg(0xB), Reg(0xA))
-]);
+]).unwrap();
 ```
 
+Instructions can also be encoded with `to_u16` or `to_bytes`, which is useful for generating programs to `load`.
+
 ## Custom input and output
 
 To get keypresses, you must implement `EmulatorInput` and `EmulatorOutput`,
 which represent somewhere to get keyboard input from and a screen respectively.
-These tell the emulator how to get the currently presses keys, and how to draw to the screen.
+These tell the emulator how to get the currently pressed keys, and how to draw to the screen.
+Since any number of keys can be held at once, `pressed_keys` returns them as a bitmask with bit `n` set while key `n` is held,
+and `poll_event` reports each `KeyEvent::Pressed` and `KeyEvent::Released` as it happens.
+`FX0A` waits for a key to be pressed and released by executing itself again until it has been,
+so timers and the frontend keep running in the meantime.
 Take a look at `src/emulator/input.rs` and `src/emulator/output.rs` to see how to implement this, then do the following.
 
 ```ignore
@@ -59,7 +165,58 @@ let mut emulator = Emulator::new(MyInput::new(), MyOutput::new());
 ```
 
 You can then implement the emulator using your own custom frontend, as done with crossterm in crossterm_frontend.
+
+## Sound
+
+The buzzer sounds while the sound timer is active.
+To hear it, implement `EmulatorAudio` from `src/emulator/audio.rs` and pass it to `Emulator::with_audio`.
+It is told once per frame whether the buzzer is on, and when an XO-CHIP program changes its audio pattern.
+`PcmAudio` renders the sound to samples that can be saved as a WAV file,
+which `no_frontend` does when given `--wav <file>`.
+
+## Save states
+
+`emulator.snapshot()` captures the full state of the emulator, including the screen,
+and `emulator.restore(&snapshot)` brings it back.
+Snapshots can be saved with `snapshot.to_bytes()` and read with `Snapshot::from_bytes`,
+and only restore into an emulator that has loaded the same program.
+
+To rewind, push a snapshot to a `Rewind` every frame, and pop them off again to step back.
+It stores the differences between snapshots, and drops the oldest ones to stay within its memory budget.
+
+## Tracing
+
+`emulator.set_tracer(Some(Tracer::new(writer)))` writes a line for every executed instruction,
+with its number, address, opcode and mnemonic, followed by the registers, I, the stack pointer and the timers.
+The columns have fixed widths, so traces from different runs or emulators can be compared with `diff`.
+Both frontends write a trace when given `--trace <file>`.
+
+```text
+000000001 0200 6003     LD V0, 0x03          V=03000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00
+000000002 0202 70FF     ADD V0, 0xFF         V=02000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00
+```
+
+## Movies
+
+A `Recorder` wraps the input of an emulator and records the keys held in every frame,
+and a `Replay` presents them to another emulator, changing keys only between frames.
+Stored in a `Movie` together with a checksum of the program, the seed given to `emulator.seed_rng(seed)`,
+the quirks and the number of instructions per frame, this replays a session exactly,
+which makes recordings of real play usable as regression tests.
+
+```sh
+cargo run --release --bin crossterm_frontend -- --record game.movie <program>
+cargo run --release --bin no_frontend -- --replay game.movie --trace game.trace <program>
+```
+
+`no_frontend` replays the movie as fast as it can and fails if the program faults,
+so movies can run in CI, with the trace compared against a known good one.
 */
 
+pub mod assembler;
+pub mod debugger;
+pub mod disassembler;
 pub mod emulator;
+pub mod gdb;
+pub mod octo;
 pub mod util;
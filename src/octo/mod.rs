@@ -0,0 +1,997 @@
+use crate::assembler::{AssembleError, Item, Program};
+use crate::emulator::emulator::PC_START;
+use crate::emulator::instruction::{Addr, Const, Instruction, Reg};
+use std::collections::HashMap;
+
+/// The most macro expansions in a program, to stop macros that expand themselves forever.
+const MAX_EXPANSIONS: usize = 100_000;
+
+/// A whitespace separated word of source, along with where it is.
+#[derive(Debug, Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        })
+    }
+}
+
+/// Split source into words, skipping `#` comments.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (line_no, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        let mut start = None;
+        for (index, c) in code
+            .char_indices()
+            .chain(std::iter::once((code.len(), ' ')))
+        {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(index),
+                (Some(word_start), true) => {
+                    tokens.push(Token {
+                        text: &code[word_start..index],
+                        line: line_no + 1,
+                        column: code[..word_start].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+fn register(text: &str) -> Option<u8> {
+    match text.as_bytes() {
+        [b'v' | b'V', digit] => (*digit as char).to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// A condition of an `if` or `while`.
+enum Condition {
+    /// Holds if the instruction would not skip
+    Skip(Instruction),
+    /// Holds depending on the flag computed by the instructions, where `true` means VF == 0
+    Flag(Vec<Instruction>, bool),
+}
+
+impl Condition {
+    /// Instructions that skip the next instruction when the condition is `holds`.
+    fn skip_when(self, holds: bool) -> Vec<Instruction> {
+        let vf = Reg(0xF);
+        match self {
+            Condition::Skip(skip) if !holds => vec![skip],
+            Condition::Skip(skip) => vec![negate(skip)],
+            Condition::Flag(mut instructions, zero) => {
+                instructions.push(if zero == holds {
+                    Instruction::IfRegEqConst(vf, Const(0))
+                } else {
+                    Instruction::IfRegNeqConst(vf, Const(0))
+                });
+                instructions
+            }
+        }
+    }
+}
+
+/// The skip instruction that skips exactly when `skip` doesn't.
+fn negate(skip: Instruction) -> Instruction {
+    match skip {
+        Instruction::IfRegEqConst(x, nn) => Instruction::IfRegNeqConst(x, nn),
+        Instruction::IfRegNeqConst(x, nn) => Instruction::IfRegEqConst(x, nn),
+        Instruction::IfRegEqReg(x, y) => Instruction::IfRegNeqReg(x, y),
+        Instruction::IfRegNeqReg(x, y) => Instruction::IfRegEqReg(x, y),
+        Instruction::IfKeyEqVx(x) => Instruction::IfKeyNeqVx(x),
+        Instruction::IfKeyNeqVx(x) => Instruction::IfKeyEqVx(x),
+        other => other,
+    }
+}
+
+struct Macro<'a> {
+    params: Vec<Token<'a>>,
+    body: Vec<Token<'a>>,
+}
+
+/// A jump or load of I whose address is filled in later.
+struct Fixup<'a> {
+    item: usize,
+    label: Token<'a>,
+}
+
+/// An open `if ... begin` block, with the jump past the current branch.
+struct Branch {
+    jump: usize,
+    has_else: bool,
+}
+
+/// An open `loop`, with the jumps out of it from `while`.
+struct Loop {
+    start: u16,
+    breaks: Vec<usize>,
+}
+
+/// Compile an Octo program to instructions and data.
+///
+/// Supports labels, `:const`, `:alias`, `:macro`, `:calc`, `:byte` and `:call`,
+/// every statement of the CHIP-8, SUPER-CHIP and XO-CHIP instruction sets,
+/// `if ... then`, `if ... begin ... else ... end`, and `loop ... while ... again`.
+///
+/// `:calc` expressions are evaluated right to left with no operator precedence,
+/// like in Octo, so use parentheses to group them.
+pub fn compile(source: &str) -> Result<Program, AssembleError> {
+    let mut tokens = tokenize(source);
+    tokens.reverse();
+    let mut compiler = Compiler {
+        tokens,
+        items: Vec::new(),
+        address: PC_START as usize,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        branches: Vec::new(),
+        loops: Vec::new(),
+        expansions: 0,
+        started: false,
+        last: None,
+    };
+
+    while let Some(token) = compiler.tokens.pop() {
+        compiler.last = Some(token);
+        compiler.statement(token)?;
+    }
+    compiler.finish()
+}
+
+struct Compiler<'a> {
+    /// The remaining tokens, in reverse
+    tokens: Vec<Token<'a>>,
+    items: Vec<(u16, Item)>,
+    address: usize,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, f64>,
+    aliases: HashMap<&'a str, u8>,
+    macros: HashMap<&'a str, Macro<'a>>,
+    fixups: Vec<Fixup<'a>>,
+    branches: Vec<Branch>,
+    loops: Vec<Loop>,
+    expansions: usize,
+    /// Whether anything has been placed in the program yet
+    started: bool,
+    /// The last token read, for errors at the end of the source
+    last: Option<Token<'a>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn next(&mut self) -> Result<Token<'a>, AssembleError> {
+        match self.tokens.pop() {
+            Some(token) => {
+                self.last = Some(token);
+                Ok(token)
+            }
+            None => {
+                let last = self.last.unwrap_or(Token {
+                    text: "",
+                    line: 1,
+                    column: 1,
+                });
+                last.error("unexpected end of program")
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.last().map(|token| token.text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token<'a>, AssembleError> {
+        let token = self.next()?;
+        if token.text != text {
+            return token.error(format!("expected {}, found {}", text, token.text));
+        }
+        Ok(token)
+    }
+
+    /// Execution starts at `main`, so jump there unless it comes before anything else.
+    fn start(&mut self) -> Result<(), AssembleError> {
+        if !self.started {
+            self.started = true;
+            if !self.labels.contains_key("main") {
+                let token = self.last.expect("a token was read");
+                let main = Token {
+                    text: "main",
+                    ..token
+                };
+                self.emit_fixup(Instruction::Goto(Addr(0)), main)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<usize, AssembleError> {
+        self.start()?;
+        self.items
+            .push((self.address as u16, Item::Instruction(instruction)));
+        self.advance(instruction.size() as usize)?;
+        Ok(self.items.len() - 1)
+    }
+
+    /// Emit an instruction whose address is the label, which may not be defined yet.
+    fn emit_fixup(
+        &mut self,
+        instruction: Instruction,
+        label: Token<'a>,
+    ) -> Result<(), AssembleError> {
+        let item = self.emit(instruction)?;
+        self.fixups.push(Fixup { item, label });
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AssembleError> {
+        self.start()?;
+        match self.items.last_mut() {
+            Some((address, Item::Data(data))) if *address as usize + data.len() == self.address => {
+                data.push(byte)
+            }
+            _ => self
+                .items
+                .push((self.address as u16, Item::Data(vec![byte]))),
+        }
+        self.advance(1)
+    }
+
+    fn advance(&mut self, size: usize) -> Result<(), AssembleError> {
+        self.address += size;
+        if self.address > 0x10000 {
+            let last = self.last.expect("something was emitted");
+            return last.error("program does not fit in memory");
+        }
+        Ok(())
+    }
+
+    /// Point the jump or load of I at an item at `target`.
+    fn patch(&mut self, item: usize, target: u16, token: Token) -> Result<(), AssembleError> {
+        if let (_, Item::Instruction(instruction)) = &mut self.items[item] {
+            match instruction {
+                Instruction::SetILong(addr) => *addr = Addr(target),
+                Instruction::Goto(addr)
+                | Instruction::Call(addr)
+                | Instruction::SetI(addr)
+                | Instruction::SetPcToV0PlusAddr(addr) => {
+                    if target > 0xFFF {
+                        return token.error(format!(
+                            "{} at {:#06X} is out of reach, use i := long",
+                            token.text, target
+                        ));
+                    }
+                    *addr = Addr(target)
+                }
+                _ => unreachable!("only instructions with addresses are patched"),
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Program, AssembleError> {
+        if let Some(branch) = self.branches.last() {
+            let (address, _) = self.items[branch.jump];
+            let token = self.last.expect("a branch was opened");
+            return token.error(format!("if at {:#05X} is missing its end", address));
+        }
+        if !self.loops.is_empty() {
+            return self.last.unwrap().error("loop is missing its again");
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let target = match self.labels.get(fixup.label.text) {
+                Some(target) => *target,
+                None => {
+                    return fixup
+                        .label
+                        .error(format!("undefined label {}", fixup.label.text))
+                }
+            };
+            self.patch(fixup.item, target, fixup.label)?;
+        }
+        Ok(Program { items: self.items })
+    }
+
+    fn define(&mut self, name: Token<'a>) -> Result<(), AssembleError> {
+        let text = name.text;
+        let valid = text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            && !text.is_empty()
+            && parse_number(text).is_none();
+        if !valid || register(text).is_some() {
+            return name.error(format!("invalid name {}", text));
+        }
+        if self.labels.contains_key(text)
+            || self.constants.contains_key(text)
+            || self.aliases.contains_key(text)
+            || self.macros.contains_key(text)
+        {
+            return name.error(format!("{} is already defined", text));
+        }
+        Ok(())
+    }
+
+    fn register(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        self.as_register(token).map_or_else(
+            || token.error(format!("expected a register, found {}", token.text)),
+            Ok,
+        )
+    }
+
+    fn as_register(&self, token: Token) -> Option<u8> {
+        register(token.text).or_else(|| self.aliases.get(token.text).copied())
+    }
+
+    /// A number, constant or label that is already defined.
+    fn value(&mut self, token: Token<'a>) -> Result<f64, AssembleError> {
+        if let Some(value) = parse_number(token.text) {
+            return Ok(value);
+        }
+        if let Some(value) = self.constants.get(token.text) {
+            return Ok(*value);
+        }
+        if let Some(address) = self.labels.get(token.text) {
+            return Ok(*address as f64);
+        }
+        token.error(format!("undefined name {}", token.text))
+    }
+
+    fn bounded(&mut self, token: Token<'a>, min: i64, max: i64) -> Result<i64, AssembleError> {
+        let value = self.value(token)?.floor() as i64;
+        if value < min || value > max {
+            return token.error(format!("{} is out of range", value));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        Ok(self.bounded(token, -0x80, 0xFF)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<Const, AssembleError> {
+        let token = self.next()?;
+        Ok(Const(self.bounded(token, 0, 0xF)? as u8))
+    }
+
+    /// Emit an instruction with an address, which may be a label that is defined later.
+    fn emit_address(&mut self, instruction: Instruction) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        let is_value = parse_number(token.text).is_some()
+            || self.constants.contains_key(token.text)
+            || self.labels.contains_key(token.text);
+        if !is_value {
+            return self.emit_fixup(instruction, token);
+        }
+        let max = if let Instruction::SetILong(_) = instruction {
+            0xFFFF
+        } else {
+            0xFFF
+        };
+        let item = self.emit(instruction)?;
+        let target = self.bounded(token, 0, max)? as u16;
+        self.patch(item, target, token)
+    }
+
+    fn statement(&mut self, token: Token<'a>) -> Result<(), AssembleError> {
+        if let Some(x) = self.as_register(token) {
+            return self.register_statement(Reg(x));
+        }
+        if parse_number(token.text).is_some() || self.constants.contains_key(token.text) {
+            let byte = self.bounded(token, -0x80, 0xFF)? as u8;
+            return self.emit_byte(byte);
+        }
+
+        let simple = match token.text {
+            "clear" => Some(Instruction::ClearScreen),
+            "return" | ";" => Some(Instruction::Return),
+            "exit" => Some(Instruction::Exit),
+            "lores" => Some(Instruction::LowRes),
+            "hires" => Some(Instruction::HighRes),
+            "scroll-left" => Some(Instruction::ScrollLeft),
+            "scroll-right" => Some(Instruction::ScrollRight),
+            "audio" => Some(Instruction::LoadAudioPattern),
+            _ => None,
+        };
+        if let Some(instruction) = simple {
+            self.emit(instruction)?;
+            return Ok(());
+        }
+
+        match token.text {
+            ":" => {
+                let name = self.next()?;
+                self.define(name)?;
+                if name.text != "main" {
+                    self.start()?;
+                }
+                self.labels.insert(name.text, self.address as u16);
+            }
+            ":const" => {
+                let name = self.next()?;
+                self.define(name)?;
+                let value = self.next()?;
+                let value = self.value(value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                self.define(name)?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+            }
+            ":macro" => {
+                let name = self.next()?;
+                self.define(name)?;
+                let mut params = Vec::new();
+                while self.peek() != Some("{") {
+                    params.push(self.next()?);
+                }
+                let body = self.block()?;
+                self.macros.insert(name.text, Macro { params, body });
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.define(name)?;
+                let expression = self.block()?;
+                let value = self.calc(&expression, name)?;
+                self.constants.insert(name.text, value);
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    let expression = self.block()?;
+                    self.calc(&expression, token)?.floor() as i64
+                } else {
+                    let token = self.next()?;
+                    self.bounded(token, -0x80, 0xFF)?
+                };
+                if !(-0x80..=0xFF).contains(&value) {
+                    return token.error(format!("{} is out of range", value));
+                }
+                self.emit_byte(value as u8)?;
+            }
+            ":call" => self.emit_address(Instruction::Call(Addr(0)))?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::SelectPlanes(n))?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::SetIToBcdOfReg(Reg(x)))?;
+            }
+            "save" | "load" => {
+                let x = Reg(self.register()?);
+                let instruction = if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = Reg(self.register()?);
+                    match token.text {
+                        "save" => Instruction::SaveRegRange(x, y),
+                        _ => Instruction::LoadRegRange(x, y),
+                    }
+                } else {
+                    match token.text {
+                        "save" => Instruction::RegDump(x),
+                        _ => Instruction::RegLoad(x),
+                    }
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::SaveFlags(Reg(x)))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags(Reg(x)))?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw(Reg(x), Reg(y), n))?;
+            }
+            "jump" => self.emit_address(Instruction::Goto(Addr(0)))?,
+            "jump0" => self.emit_address(Instruction::SetPcToV0PlusAddr(Addr(0)))?,
+            "i" => {
+                let operator = self.next()?;
+                match (operator.text, self.peek()) {
+                    (":=", Some("hex")) => {
+                        self.next()?;
+                        let x = self.register()?;
+                        self.emit(Instruction::SetIToSpriteAddrVx(Reg(x)))?;
+                    }
+                    (":=", Some("bighex")) => {
+                        self.next()?;
+                        let x = self.register()?;
+                        self.emit(Instruction::SetIToBigSpriteAddrVx(Reg(x)))?;
+                    }
+                    (":=", Some("long")) => {
+                        self.next()?;
+                        self.emit_address(Instruction::SetILong(Addr(0)))?;
+                    }
+                    (":=", _) => self.emit_address(Instruction::SetI(Addr(0)))?,
+                    ("+=", _) => {
+                        let x = self.register()?;
+                        self.emit(Instruction::AddRegToI(Reg(x)))?;
+                    }
+                    _ => return operator.error(format!("unknown operator i {}", operator.text)),
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = Reg(self.register()?);
+                self.emit(match token.text {
+                    "delay" => Instruction::SetDelayTimerToReg(x),
+                    "buzzer" => Instruction::SetSoundTimerToReg(x),
+                    _ => Instruction::SetPitchToReg(x),
+                })?;
+            }
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text {
+                    "then" => {
+                        for instruction in condition.skip_when(false) {
+                            self.emit(instruction)?;
+                        }
+                    }
+                    "begin" => {
+                        for instruction in condition.skip_when(true) {
+                            self.emit(instruction)?;
+                        }
+                        let jump = self.emit(Instruction::Goto(Addr(0)))?;
+                        self.branches.push(Branch {
+                            jump,
+                            has_else: false,
+                        });
+                    }
+                    _ => return keyword.error("expected then or begin"),
+                }
+            }
+            "else" => {
+                let jump = self.emit(Instruction::Goto(Addr(0)))?;
+                let address = self.address as u16;
+                let branch = match self.branches.last_mut() {
+                    Some(branch) if !branch.has_else => branch,
+                    _ => return token.error("else without if ... begin"),
+                };
+                let previous = std::mem::replace(&mut branch.jump, jump);
+                branch.has_else = true;
+                self.patch(previous, address, token)?;
+            }
+            "end" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return token.error("end without if ... begin"),
+                };
+                self.patch(branch.jump, self.address as u16, token)?;
+            }
+            "loop" => self.loops.push(Loop {
+                start: self.address as u16,
+                breaks: Vec::new(),
+            }),
+            "while" => {
+                if self.loops.is_empty() {
+                    return token.error("while outside of loop");
+                }
+                let condition = self.condition()?;
+                for instruction in condition.skip_when(true) {
+                    self.emit(instruction)?;
+                }
+                let jump = self.emit(Instruction::Goto(Addr(0)))?;
+                self.loops.last_mut().unwrap().breaks.push(jump);
+            }
+            "again" => {
+                let lp = match self.loops.pop() {
+                    Some(lp) => lp,
+                    None => return token.error("again without loop"),
+                };
+                let jump = self.emit(Instruction::Goto(Addr(0)))?;
+                self.patch(jump, lp.start, token)?;
+                for jump in lp.breaks {
+                    self.patch(jump, self.address as u16, token)?;
+                }
+            }
+            _ => {
+                if let Some(m) = self.macros.get(token.text) {
+                    let params = m.params.clone();
+                    let body = m.body.clone();
+                    return self.expand(token, &params, &body);
+                }
+                if token.text.starts_with(':') {
+                    return token.error(format!("unknown directive {}", token.text));
+                }
+                // Any other name is a subroutine call
+                self.emit_fixup(Instruction::Call(Addr(0)), token)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Statements that start with a register, such as `v0 := 5` or `v1 += v2`.
+    fn register_statement(&mut self, x: Reg) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let y = self.as_register(operand).map(Reg);
+        let instruction = match (operator.text, y, operand.text) {
+            (":=", Some(y), _) => Instruction::SetRegToReg(x, y),
+            (":=", None, "key") => Instruction::SetRegToGetKey(x),
+            (":=", None, "delay") => Instruction::SetRegToDelayTimer(x),
+            (":=", None, "random") => {
+                let nn = self.byte()?;
+                Instruction::SetVxRand(x, Const(nn))
+            }
+            (":=", None, _) => {
+                let nn = self.bounded(operand, -0x80, 0xFF)? as u8;
+                Instruction::SetRegToConst(x, Const(nn))
+            }
+            ("+=", Some(y), _) => Instruction::IncRegByReg(x, y),
+            ("+=", None, _) => {
+                let nn = self.bounded(operand, -0x80, 0xFF)? as u8;
+                Instruction::IncRegByConst(x, Const(nn))
+            }
+            ("-=", Some(y), _) => Instruction::DecRegByReg(x, y),
+            ("-=", None, _) => {
+                let nn = self.bounded(operand, -0xFF, 0x80)?;
+                Instruction::IncRegByConst(x, Const(nn.wrapping_neg() as u8))
+            }
+            ("=-", Some(y), _) => Instruction::SetVxVyMinusVx(x, y),
+            ("|=", Some(y), _) => Instruction::BitwiseOr(x, y),
+            ("&=", Some(y), _) => Instruction::BitwiseAnd(x, y),
+            ("^=", Some(y), _) => Instruction::BitwiseXor(x, y),
+            (">>=", Some(y), _) => Instruction::BitshiftRight(x, y),
+            ("<<=", Some(y), _) => Instruction::BitshiftLeft(x, y),
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None, _) => {
+                return operand.error(format!("expected a register, found {}", operand.text))
+            }
+            _ => return operator.error(format!("unknown operator {}", operator.text)),
+        };
+        self.emit(instruction)?;
+        Ok(())
+    }
+
+    /// A condition such as `v0 == 5`, `v1 key` or `v2 < v3`.
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let x = Reg(self.register()?);
+        let operator = self.next()?;
+        match operator.text {
+            "key" => return Ok(Condition::Skip(Instruction::IfKeyNeqVx(x))),
+            "-key" => return Ok(Condition::Skip(Instruction::IfKeyEqVx(x))),
+            _ => {}
+        }
+
+        let operand = self.next()?;
+        let y = self.as_register(operand).map(Reg);
+        let nn = match y {
+            Some(_) => Const(0),
+            None => Const(self.bounded(operand, -0x80, 0xFF)? as u8),
+        };
+        let condition = match (operator.text, y) {
+            // Equality holds when the opposite skip doesn't skip
+            ("==", Some(y)) => Condition::Skip(Instruction::IfRegNeqReg(x, y)),
+            ("==", None) => Condition::Skip(Instruction::IfRegNeqConst(x, nn)),
+            ("!=", Some(y)) => Condition::Skip(Instruction::IfRegEqReg(x, y)),
+            ("!=", None) => Condition::Skip(Instruction::IfRegEqConst(x, nn)),
+            // Compare by subtracting into VF, which leaves VF as 1 when there is no borrow
+            ("<" | ">=" | ">" | "<=", _) => {
+                let vf = Reg(0xF);
+                let (a, b) = match operator.text {
+                    "<" | ">=" => (Some(x), y),
+                    _ => (y, Some(x)),
+                };
+                // Computes a >= b into VF
+                let instructions = match (a, b) {
+                    (Some(a), Some(b)) => vec![
+                        Instruction::SetRegToReg(vf, a),
+                        Instruction::DecRegByReg(vf, b),
+                    ],
+                    (Some(a), None) => vec![
+                        Instruction::SetRegToConst(vf, nn),
+                        Instruction::SetVxVyMinusVx(vf, a),
+                    ],
+                    (None, Some(b)) => vec![
+                        Instruction::SetRegToConst(vf, nn),
+                        Instruction::DecRegByReg(vf, b),
+                    ],
+                    (None, None) => unreachable!("one side is always Vx"),
+                };
+                Condition::Flag(instructions, matches!(operator.text, "<" | ">"))
+            }
+            _ => return operator.error(format!("unknown comparison {}", operator.text)),
+        };
+        Ok(condition)
+    }
+
+    /// The tokens between `{` and the matching `}`.
+    fn block(&mut self) -> Result<Vec<Token<'a>>, AssembleError> {
+        self.expect("{")?;
+        let mut depth = 0;
+        let mut body = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token.text {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    /// Replace a macro invocation with its body, with the parameters replaced by the arguments.
+    fn expand(
+        &mut self,
+        name: Token<'a>,
+        params: &[Token<'a>],
+        body: &[Token<'a>],
+    ) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return name.error("too many macro expansions");
+        }
+        let mut args = HashMap::new();
+        for param in params {
+            args.insert(param.text, self.next()?);
+        }
+        for token in body.iter().rev() {
+            self.tokens.push(*args.get(token.text).unwrap_or(token));
+        }
+        Ok(())
+    }
+
+    /// Evaluate a `:calc` expression.
+    fn calc(&mut self, expression: &[Token<'a>], name: Token<'a>) -> Result<f64, AssembleError> {
+        let mut tokens: Vec<_> = expression.iter().rev().copied().collect();
+        let value = self.expression(&mut tokens, name)?;
+        match tokens.last() {
+            Some(token) => token.error(format!("unexpected {}", token.text)),
+            None => Ok(value),
+        }
+    }
+
+    /// An expression, evaluated right to left.
+    fn expression(
+        &mut self,
+        tokens: &mut Vec<Token<'a>>,
+        end: Token<'a>,
+    ) -> Result<f64, AssembleError> {
+        let left = self.term(tokens, end)?;
+        let operator = match tokens.last() {
+            Some(token) if token.text != ")" => *token,
+            _ => return Ok(left),
+        };
+        tokens.pop();
+        let right = self.expression(tokens, end)?;
+        let int = |value: f64| value as i64;
+        Ok(match operator.text {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (int(left) & int(right)) as f64,
+            "|" => (int(left) | int(right)) as f64,
+            "^" => (int(left) ^ int(right)) as f64,
+            "<<" => int(left).checked_shl(int(right) as u32).unwrap_or(0) as f64,
+            ">>" => int(left).checked_shr(int(right) as u32).unwrap_or(0) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            _ => return operator.error(format!("unknown operator {}", operator.text)),
+        })
+    }
+
+    /// A value, a parenthesized expression, or a unary operator applied to a term.
+    fn term(&mut self, tokens: &mut Vec<Token<'a>>, end: Token<'a>) -> Result<f64, AssembleError> {
+        let token = match tokens.pop() {
+            Some(token) => token,
+            None => return end.error("expected a value"),
+        };
+        let unary: Option<fn(f64) -> f64> = match token.text {
+            "-" => Some(|x| -x),
+            "~" => Some(|x| !(x as i64) as f64),
+            "!" => Some(|x| (x == 0.0) as i64 as f64),
+            "floor" => Some(f64::floor),
+            "ceil" => Some(f64::ceil),
+            "abs" => Some(f64::abs),
+            "sqrt" => Some(f64::sqrt),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "log" => Some(f64::ln),
+            _ => None,
+        };
+        if let Some(unary) = unary {
+            return Ok(unary(self.term(tokens, end)?));
+        }
+        match token.text {
+            "(" => {
+                let value = self.expression(tokens, end)?;
+                match tokens.pop() {
+                    Some(Token { text: ")", .. }) => Ok(value),
+                    _ => token.error("unclosed parenthesis"),
+                }
+            }
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            "HERE" => Ok(self.address as f64),
+            _ => self.value(token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::emulator::Emulator;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        compile(source).unwrap().to_bytes()
+    }
+
+    fn error(source: &str) -> (usize, usize) {
+        let error = compile(source).unwrap_err();
+        (error.line, error.column)
+    }
+
+    /// Run a program until it exits, and get its registers.
+    fn run(source: &str) -> [u8; 16] {
+        let mut emulator = Emulator::dummy();
+        emulator.load(&bytes(source));
+        for _ in 0..10_000 {
+            emulator.step().unwrap();
+            if emulator.has_exited() {
+                return emulator.snapshot().registers;
+            }
+        }
+        panic!("program did not exit");
+    }
+
+    #[test]
+    fn main_is_jumped_to_unless_first() {
+        assert_eq!(bytes(": main clear"), [0x00, 0xE0]);
+        assert_eq!(
+            bytes(": sub return : main sub"),
+            [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]
+        );
+    }
+
+    #[test]
+    fn statements_compile_to_instructions() {
+        let source = "
+            : main
+                v0 := 5       # comment
+                v1 += v0
+                v2 -= 1
+                i := sprite
+                sprite v0 v1 5
+                i := long sprite
+                delay := v2
+                v3 := random 0xF0
+                save v0 - v3
+                ;
+            : sprite 0xF0 0b10010000
+        ";
+        assert_eq!(
+            bytes(source),
+            [
+                0x60, 0x05, 0x81, 0x04, 0x72, 0xFF, 0xA2, 0x16, 0xD0, 0x15, 0xF0, 0x00, 0x02, 0x16,
+                0xF2, 0x15, 0xC3, 0xF0, 0x50, 0x32, 0x00, 0xEE, 0xF0, 0x90
+            ]
+        );
+    }
+
+    #[test]
+    fn macros_calc_and_aliases() {
+        let source = "
+            :alias counter v4
+            :const BASE 10
+            :calc DOUBLE { BASE * 2 + 1 }
+            :macro set reg value { reg := value }
+            : main
+                set counter DOUBLE
+                :byte { ( 1 + 2 ) * 3 }
+        ";
+        // Right to left, so 10 * (2 + 1)
+        assert_eq!(bytes(source), [0x64, 30, 9]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "
+            : main
+                v0 := 0
+                v1 := 0
+                loop
+                    v0 += 1
+                    if v0 == 3 then v1 += 10
+                    if v0 > 5 begin
+                        v2 := 1
+                    else
+                        v3 += 1
+                    end
+                    while v0 < 8
+                again
+                exit
+        ";
+        let registers = run(source);
+        assert_eq!(registers[..4], [8, 10, 1, 5]);
+    }
+
+    #[test]
+    fn comparisons_with_constants_and_registers() {
+        let source = "
+            : main
+                v0 := 4
+                v1 := 7
+                if v0 < 5 then v2 += 1
+                if v0 <= 4 then v2 += 1
+                if v0 >= 5 then v3 += 1
+                if 4 then v3 += 0
+                if v1 > v0 then v2 += 1
+                if v1 <= v0 then v3 += 1
+                exit
+        ";
+        // Conditions need a register on the left
+        assert_eq!(error(source), (8, 20));
+        let source = source.replace("if 4 then v3 += 0", "");
+        let registers = run(&source);
+        assert_eq!(registers[2..4], [3, 0]);
+    }
+
+    #[test]
+    fn errors_have_line_and_column() {
+        assert_eq!(error(": main\n  jump nowhere"), (2, 8));
+        assert_eq!(error(": main\n  v0 := 300"), (2, 9));
+        assert_eq!(error(": main\n  loop"), (2, 3));
+        assert_eq!(error(": main\n  : main"), (2, 5));
+        assert_eq!(error("clear"), (1, 1));
+    }
+}
@@ -1 +1,3 @@
 pub mod bit_splitter;
+pub mod crc32;
+pub mod reader;
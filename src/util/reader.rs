@@ -0,0 +1,80 @@
+use std::convert::TryInto;
+
+/// Reads values stored in little endian from the start of a byte slice,
+/// for deserializing the file formats of snapshots and movies.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+/// An error from reading, which each file format maps to its own error type.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ReadError {
+    /// The bytes end before the value does.
+    Truncated,
+    /// A boolean is neither 0 nor 1.
+    InvalidBoolean,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    /// The bytes that have not been read yet.
+    pub fn rest(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], ReadError> {
+        if self.bytes.len() < len {
+            return Err(ReadError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], ReadError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ReadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, ReadError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, ReadError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, ReadError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ReadError::InvalidBoolean),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_little_endian_values() {
+        let mut reader = Reader::new(&[1, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12, 2]);
+        assert_eq!(Ok(true), reader.bool());
+        assert_eq!(Ok(0x1234), reader.u16());
+        assert_eq!(Ok(0x1234_5678), reader.u32());
+        assert_eq!(&[2], reader.rest());
+        assert_eq!(Err(ReadError::InvalidBoolean), reader.bool());
+        assert_eq!(Err(ReadError::Truncated), reader.u8());
+    }
+}
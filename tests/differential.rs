@@ -0,0 +1,167 @@
+//! Runs random sequences of instructions on both `Emulator` and an independent reference interpreter,
+//! comparing their state after every step. When they disagree,
+//! proptest shrinks the sequence down to the shortest one that still does.
+
+mod reference;
+
+use chip_8::emulator::input::DummyInput;
+use chip_8::emulator::instruction::{Addr, Const, Instruction, Reg};
+use chip_8::emulator::output::{DummyOutput, EmulatorOutput};
+use chip_8::emulator::quirks::Quirks;
+use chip_8::emulator::Emulator;
+use proptest::prelude::*;
+use reference::{Reference, HEIGHT, WIDTH};
+
+fn reg() -> impl Strategy<Value = Reg> {
+    (0..16u8).prop_map(Reg)
+}
+
+fn byte() -> impl Strategy<Value = Const> {
+    any::<u8>().prop_map(Const)
+}
+
+fn addr() -> impl Strategy<Value = Addr> {
+    (0..0x1000u16).prop_map(Addr)
+}
+
+/// Any instruction the reference interpreter supports.
+fn instruction() -> impl Strategy<Value = Instruction> {
+    use Instruction::*;
+    prop_oneof![
+        Just(ClearScreen),
+        Just(Return),
+        addr().prop_map(Goto),
+        addr().prop_map(Call),
+        (reg(), byte()).prop_map(|(x, nn)| IfRegEqConst(x, nn)),
+        (reg(), byte()).prop_map(|(x, nn)| IfRegNeqConst(x, nn)),
+        (reg(), reg()).prop_map(|(x, y)| IfRegEqReg(x, y)),
+        (reg(), byte()).prop_map(|(x, nn)| SetRegToConst(x, nn)),
+        (reg(), byte()).prop_map(|(x, nn)| IncRegByConst(x, nn)),
+        (reg(), reg()).prop_map(|(x, y)| SetRegToReg(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| BitwiseOr(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| BitwiseAnd(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| BitwiseXor(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| IncRegByReg(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| DecRegByReg(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| BitshiftRight(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| SetVxVyMinusVx(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| BitshiftLeft(x, y)),
+        (reg(), reg()).prop_map(|(x, y)| IfRegNeqReg(x, y)),
+        addr().prop_map(SetI),
+        addr().prop_map(SetPcToV0PlusAddr),
+        (reg(), reg(), 1..16u8).prop_map(|(x, y, n)| Draw(x, y, Const(n))),
+        reg().prop_map(IfKeyEqVx),
+        reg().prop_map(IfKeyNeqVx),
+        reg().prop_map(SetRegToDelayTimer),
+        reg().prop_map(SetDelayTimerToReg),
+        reg().prop_map(SetSoundTimerToReg),
+        reg().prop_map(AddRegToI),
+        reg().prop_map(SetIToSpriteAddrVx),
+        reg().prop_map(SetIToBcdOfReg),
+        reg().prop_map(RegDump),
+        reg().prop_map(RegLoad),
+    ]
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    let presets: Vec<Quirks> = Quirks::PRESETS.iter().map(|(_, quirks)| *quirks).collect();
+    prop_oneof![Just(Quirks::default()), proptest::sample::select(presets)]
+}
+
+/// Describe the first difference between the emulator and the reference, if any.
+fn difference(
+    emulator: &Emulator<DummyInput, DummyOutput>,
+    reference: &Reference,
+) -> Option<String> {
+    let (delay_timer, sound_timer) = emulator.timers();
+    let fields = [
+        (
+            "registers",
+            format!("{:02X?}", emulator.registers()),
+            format!("{:02X?}", reference.v),
+        ),
+        (
+            "I",
+            format!("{:#X}", emulator.i()),
+            format!("{:#X}", reference.i),
+        ),
+        (
+            "PC",
+            format!("{:#X}", emulator.program_counter()),
+            format!("{:#X}", reference.pc),
+        ),
+        (
+            "stack",
+            format!("{:X?}", emulator.stack()),
+            format!("{:X?}", reference.stack),
+        ),
+        (
+            "delay timer",
+            delay_timer.to_string(),
+            reference.delay_timer.to_string(),
+        ),
+        (
+            "sound timer",
+            sound_timer.to_string(),
+            reference.sound_timer.to_string(),
+        ),
+    ];
+    for (name, actual, expected) in &fields {
+        if actual != expected {
+            return Some(format!("{} is {}, expected {}", name, actual, expected));
+        }
+    }
+
+    let memory = emulator.memory().iter().zip(&reference.memory).enumerate();
+    for (address, (actual, expected)) in memory {
+        if actual != expected {
+            return Some(format!(
+                "memory at {:#X} is {:#04X}, expected {:#04X}",
+                address, actual, expected
+            ));
+        }
+    }
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let actual = emulator.output().get(x, y) != 0;
+            if actual != reference.screen[y][x] {
+                return Some(format!(
+                    "pixel ({}, {}) is {}, expected {}",
+                    x, y, actual, !actual
+                ));
+            }
+        }
+    }
+    None
+}
+
+proptest! {
+    #[test]
+    fn emulator_matches_reference(
+        quirks in quirks(),
+        instructions in proptest::collection::vec(instruction(), 1..64),
+    ) {
+        let mut emulator = Emulator::with_quirks(DummyInput, DummyOutput::new(), quirks);
+        let mut reference = Reference::new(emulator.memory().to_vec(), quirks);
+
+        for (step, instruction) in instructions.iter().enumerate() {
+            let actual = emulator.execute_single(*instruction);
+            let expected = reference.execute(*instruction);
+            prop_assert_eq!(
+                actual.is_err(),
+                expected.is_err(),
+                "step {} ({}) faulted in only one of them: {:?}",
+                step,
+                instruction,
+                actual
+            );
+            if let Some(difference) = difference(&emulator, &reference) {
+                prop_assert!(false, "after step {} ({}): {}", step, instruction, difference);
+            }
+            if actual.is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! A CHIP-8 interpreter written from the specification at
+//! https://en.wikipedia.org/wiki/CHIP-8#Opcode_table,
+//! without sharing any code with `Emulator`, to compare it against.
+//!
+//! Only the original CHIP-8 instructions are supported,
+//! and `SetVxRand` and `SetRegToGetKey` are left out since they aren't deterministic.
+
+use chip_8::emulator::instruction::{Addr, Const, Instruction, Reg};
+use chip_8::emulator::quirks::{LoadStoreIncrement, Quirks};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+/// The deepest the stack can get before a call faults.
+/// The emulator has 256 slots, but its stack pointer is a single byte.
+const MAX_STACK_DEPTH: usize = 255;
+
+pub struct Reference {
+    pub memory: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub screen: [[bool; WIDTH]; HEIGHT],
+    quirks: Quirks,
+}
+
+/// An instruction that could not be executed, which leaves the state unchanged.
+#[derive(Debug)]
+pub struct Fault;
+
+impl Reference {
+    /// Start executing at 0x200 with the given memory, which should already contain the font.
+    pub fn new(memory: Vec<u8>, quirks: Quirks) -> Reference {
+        Reference {
+            memory,
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            screen: [[false; WIDTH]; HEIGHT],
+            quirks,
+        }
+    }
+
+    /// The `len` bytes of memory starting at I, if they are all in memory.
+    fn at_i(&self, len: usize) -> Result<std::ops::Range<usize>, Fault> {
+        let start = self.i as usize;
+        if start + len > self.memory.len() {
+            return Err(Fault);
+        }
+        Ok(start..start + len)
+    }
+
+    /// Skip an instruction, where `F000 NNNN` counts as one.
+    fn skip(&mut self) {
+        let pc = self.pc as usize;
+        let long =
+            pc + 1 < self.memory.len() && self.memory[pc] == 0xF0 && self.memory[pc + 1] == 0x00;
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), Fault> {
+        use Instruction::*;
+
+        // Check for faults first, so a faulting instruction changes nothing
+        match instruction {
+            Return if self.stack.is_empty() => return Err(Fault),
+            Call(_) if self.stack.len() >= MAX_STACK_DEPTH => return Err(Fault),
+            AddRegToI(Reg(x)) if self.i as usize + self.v[x as usize] as usize > 0xFFFF => {
+                return Err(Fault)
+            }
+            Draw(_, _, Const(n)) => {
+                self.at_i(n as usize)?;
+            }
+            SetIToBcdOfReg(_) => {
+                self.at_i(3)?;
+            }
+            RegDump(Reg(x)) | RegLoad(Reg(x)) => {
+                self.at_i(x as usize + 1)?;
+            }
+            _ => {}
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        let v = &mut self.v;
+        match instruction {
+            ClearScreen => self.screen = [[false; WIDTH]; HEIGHT],
+            Return => self.pc = self.stack.pop().unwrap(),
+            Goto(Addr(nnn)) => self.pc = nnn,
+            Call(Addr(nnn)) => {
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            IfRegEqConst(Reg(x), Const(nn)) => {
+                if v[x as usize] == nn {
+                    self.skip();
+                }
+            }
+            IfRegNeqConst(Reg(x), Const(nn)) => {
+                if v[x as usize] != nn {
+                    self.skip();
+                }
+            }
+            IfRegEqReg(Reg(x), Reg(y)) => {
+                if v[x as usize] == v[y as usize] {
+                    self.skip();
+                }
+            }
+            IfRegNeqReg(Reg(x), Reg(y)) => {
+                if v[x as usize] != v[y as usize] {
+                    self.skip();
+                }
+            }
+            SetRegToConst(Reg(x), Const(nn)) => v[x as usize] = nn,
+            IncRegByConst(Reg(x), Const(nn)) => v[x as usize] = v[x as usize].wrapping_add(nn),
+            SetRegToReg(Reg(x), Reg(y)) => v[x as usize] = v[y as usize],
+            BitwiseOr(Reg(x), Reg(y)) | BitwiseAnd(Reg(x), Reg(y)) | BitwiseXor(Reg(x), Reg(y)) => {
+                let (a, b) = (v[x as usize], v[y as usize]);
+                v[x as usize] = match instruction {
+                    BitwiseOr(..) => a | b,
+                    BitwiseAnd(..) => a & b,
+                    _ => a ^ b,
+                };
+                if self.quirks.logic_resets_vf {
+                    v[0xF] = 0;
+                }
+            }
+            // The flag is written last, so it wins when X is F
+            IncRegByReg(Reg(x), Reg(y)) => {
+                let sum = v[x as usize] as u16 + v[y as usize] as u16;
+                v[x as usize] = sum as u8;
+                v[0xF] = (sum > 0xFF) as u8;
+            }
+            DecRegByReg(Reg(x), Reg(y)) => {
+                let (a, b) = (v[x as usize], v[y as usize]);
+                v[x as usize] = a.wrapping_sub(b);
+                v[0xF] = (a >= b) as u8;
+            }
+            SetVxVyMinusVx(Reg(x), Reg(y)) => {
+                let (a, b) = (v[x as usize], v[y as usize]);
+                v[x as usize] = b.wrapping_sub(a);
+                v[0xF] = (b >= a) as u8;
+            }
+            BitshiftRight(Reg(x), Reg(y)) | BitshiftLeft(Reg(x), Reg(y)) => {
+                let value = if self.quirks.shift_uses_vy {
+                    v[y as usize]
+                } else {
+                    v[x as usize]
+                };
+                let (result, flag) = match instruction {
+                    BitshiftRight(..) => (value / 2, value % 2),
+                    _ => (value.wrapping_mul(2), value / 0x80),
+                };
+                v[x as usize] = result;
+                v[0xF] = flag;
+            }
+            SetI(Addr(nnn)) => self.i = nnn,
+            SetPcToV0PlusAddr(Addr(nnn)) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    v[(nnn >> 8) as usize]
+                } else {
+                    v[0]
+                };
+                self.pc = nnn + offset as u16;
+            }
+            Draw(Reg(x), Reg(y), Const(n)) => {
+                let left = v[x as usize] as usize % WIDTH;
+                let top = v[y as usize] as usize % HEIGHT;
+                let mut collision = false;
+                for row in 0..n as usize {
+                    let byte = self.memory[self.i as usize + row];
+                    for column in 0..8 {
+                        if byte & (0x80 >> column) == 0 {
+                            continue;
+                        }
+                        let (px, py) = (left + column, top + row);
+                        if self.quirks.clip_sprites && (px >= WIDTH || py >= HEIGHT) {
+                            continue;
+                        }
+                        let pixel = &mut self.screen[py % HEIGHT][px % WIDTH];
+                        collision |= *pixel;
+                        *pixel = !*pixel;
+                    }
+                }
+                self.v[0xF] = collision as u8;
+            }
+            // No keys are ever pressed
+            IfKeyEqVx(_) => {}
+            IfKeyNeqVx(_) => self.skip(),
+            SetRegToDelayTimer(Reg(x)) => v[x as usize] = self.delay_timer,
+            SetDelayTimerToReg(Reg(x)) => self.delay_timer = v[x as usize],
+            SetSoundTimerToReg(Reg(x)) => self.sound_timer = v[x as usize],
+            AddRegToI(Reg(x)) => self.i += v[x as usize] as u16,
+            SetIToSpriteAddrVx(Reg(x)) => self.i = 5 * (v[x as usize] & 0xF) as u16,
+            SetIToBcdOfReg(Reg(x)) => {
+                let value = v[x as usize];
+                let i = self.i as usize;
+                self.memory[i] = value / 100;
+                self.memory[i + 1] = value / 10 % 10;
+                self.memory[i + 2] = value % 10;
+            }
+            RegDump(Reg(x)) | RegLoad(Reg(x)) => {
+                for n in 0..=x as usize {
+                    let address = self.i as usize + n;
+                    match instruction {
+                        RegDump(_) => self.memory[address] = self.v[n],
+                        _ => self.v[n] = self.memory[address],
+                    }
+                }
+                self.i = self.i.wrapping_add(match self.quirks.load_store_increment {
+                    LoadStoreIncrement::Unchanged => 0,
+                    LoadStoreIncrement::ByX => x as u16,
+                    LoadStoreIncrement::ByXPlusOne => x as u16 + 1,
+                });
+            }
+            other => panic!("{:?} is not supported by the reference interpreter", other),
+        }
+        Ok(())
+    }
+}